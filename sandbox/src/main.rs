@@ -1,10 +1,23 @@
 extern crate piston_window;
 extern crate velox_core;
+extern crate velox_script;
+extern crate velox_font as font;
+extern crate velox_hud;
 
-use velox_core::obj::{Vector2, RotatableObject, Planet, Player};
+use std::env::args;
+use std::collections::BTreeMap;
+
+use velox_core::obj::{Vector2, RotatableObject, Planet, Player, Laser};
+use velox_core::net::Idx;
+use velox_core::weapon::WeaponId;
+use velox_core::control::Radar;
 
 use piston_window::*;
 
+mod program;
+
+use program::Bot;
+
 macro_rules! assets {
     ($base:ident; $($tex:ident),*) => {
         struct $base {
@@ -42,6 +55,9 @@ pub fn pos_rot_mat(x: f64, y: f64, width: f64, height: f64, w: f64, h: f64, rot:
     [[1., 0., 0.], [0., 1., 0.]].trans(x+w, y+h).rot_rad(rot).trans(-width, -height)
 }
 
+/// Seconds between checking whether programs have changed
+const RELOAD_INTERVAL: f64 = 0.5;
+
 fn main() {
     let mut window: PistonWindow =
     WindowSettings::new(format!("Space Sandbox {}", env!("CARGO_PKG_VERSION")), [1200, 900])
@@ -51,7 +67,7 @@ fn main() {
     let assets = Assets::new(&mut window);
     let mut planets = Vec::<Planet>::new();
     let mut player = Player::default();
-    let mut lasers = Vec::<RotatableObject>::new();
+    let mut lasers = Vec::<Laser>::new();
 
     let mut up = false;
     let mut down = false;
@@ -61,9 +77,23 @@ fn main() {
     let mut rw = 600.;
     let mut rh = 450.;
 
+    let mut weapon: WeaponId = 0;
+
     let mut cr_pos = Vector2::default();
     let mut creating = false;
 
+    // Every program given gets a ship of its own, lined up below the one flown by keyboard
+    let mut bots: Vec<_> = args().skip(1).enumerate().map(|(n, spec)| {
+        let player = Player {
+            obj: RotatableObject::new(Vector2(-300. + 150. * n as f32, 200.), Vector2::default(), 0.),
+            .. Player::default()
+        };
+        Bot::new(spec, player)
+    }).collect();
+    let mut since_reload = RELOAD_INTERVAL;
+    let mut time = 0.;
+    let mut overlay = false;
+
     while let Some(e) = window.next() {
         match e {
             Event::Input(Input::Button(b)) => {
@@ -71,13 +101,14 @@ fn main() {
 
                 match b.button {
                     Button::Keyboard(Key::Space) if press => {
-                        let dir = Vector2::unit_vector(player.obj.rotation);
-
-                        let new_laser = RotatableObject::new(player.obj.pos() + 42. * dir,
-                            player.obj.vel() + 400. * dir, player.obj.rotation);
-
-                        lasers.push(new_laser);
+                        lasers.extend(player.try_fire(0, weapon));
                     }
+                    Button::Keyboard(Key::D1) => weapon = 0,
+                    Button::Keyboard(Key::D2) => weapon = 1,
+                    Button::Keyboard(Key::D3) => weapon = 2,
+                    Button::Keyboard(Key::D4) => weapon = 3,
+                    Button::Keyboard(Key::D5) => weapon = 4,
+                    Button::Keyboard(Key::O) if press => overlay = !overlay,
                     Button::Keyboard(Key::Up) | Button::Keyboard(Key::W) => up = press,
                     Button::Keyboard(Key::Down) | Button::Keyboard(Key::S) => down = press,
                     Button::Keyboard(Key::Left) | Button::Keyboard(Key::A) => left = press,
@@ -121,18 +152,66 @@ fn main() {
                             x as f64, y as f64, 16., 16., w, h, player.obj.rotation as f64)), g)
                     }
 
+                    for (n, bot) in bots.iter().enumerate() {
+                        let (x, y) = bot.player.obj.pos().into();
+                        image(&assets.ship, c.transform.append_transform(pos_rot_mat(
+                            x as f64, y as f64, 16., 16., w, h, bot.player.obj.rotation as f64)), g);
+                        font::text(&(n + 1).to_string(), [1., 1., 1., 0.8], x as f64 + w - 3., y as f64 + h - 30., 2., c.transform, g);
+                    }
+
+                    // What every program showed about its last tick
+                    if overlay {
+                        for bot in bots.iter() {
+                            velox_hud::debug(&bot.debug, &bot.log, bot.player.obj.pos(), c.transform.trans(w, h), g);
+                        }
+                    }
+
                     for laser in lasers.iter() {
-                        let (x, y) = laser.pos().into();
+                        let (x, y) = laser.obj.pos().into();
                         image(&assets.laser, c.transform.append_transform(pos_rot_mat(
-                            x as f64, y as f64, 16., 16., w, h, laser.rotation as f64)), g)
+                            x as f64, y as f64, 16., 16., w, h, laser.obj.rotation as f64)), g)
                     }
 
-                    let hp = player.health;
-                    rectangle([0.77, 0.77, 0.77, 0.6], [0., 0., 170., 40.], c.transform, g);
-                    rectangle([0., 1., 0., 0.6], [10., 5., 30.*hp as f64, 20.], c.transform, g);
+                    velox_hud::status(&player.status, weapon, c.transform, g);
+
+                    // Every program with what's wrong with it, if anything
+                    if !bots.is_empty() {
+                        let top = 2. * h - 12. - 14. * bots.len() as f64;
+                        rectangle([0.1, 0.1, 0.1, 0.8], [0., top - 8., 2. * w, 2. * h - top + 8.], c.transform, g);
+                        for (n, bot) in bots.iter().enumerate() {
+                            let (line, colour) = match bot.error() {
+                                Some(e) => (format!("{} {}: {}", n + 1, bot.spec, e), [1., 0.3, 0.3, 1.]),
+                                None => (format!("{} {}", n + 1, bot.spec), [0.6, 1., 0.6, 1.]),
+                            };
+                            font::text(&line, colour, 8., top + 14. * n as f64, 2., c.transform, g);
+                        }
+                    }
                 });
             }
             Event::Loop(Loop::Update(u)) => {
+                time += u.dt as f32;
+                since_reload += u.dt;
+                if since_reload >= RELOAD_INTERVAL {
+                    since_reload = 0.;
+                    for bot in bots.iter_mut() {
+                        bot.reload_if_changed();
+                    }
+                }
+
+                let players: BTreeMap<Idx, Player> = Some((0, player)).into_iter()
+                    .chain(bots.iter().enumerate().map(|(n, b)| (n as Idx + 1, b.player)))
+                    .collect();
+                let planet_map = (0..).zip(planets.iter().cloned()).collect();
+                let laser_map = (0..).zip(lasers.iter().cloned()).collect();
+                let radar = Radar::new(&players, &planet_map, &laser_map);
+                for (n, bot) in bots.iter_mut().enumerate() {
+                    let i = n as Idx + 1;
+                    let controls = bot.control(&radar.sense(&players, &planet_map, &laser_map, i, time));
+                    for command in controls.commands(&bot.player, u.dt as f32) {
+                        lasers.extend(bot.player.command(i, command));
+                    }
+                }
+
                 let (mut impulse, mut rotation) = (0., 0.);
                 if up {
                     impulse += 1.;
@@ -147,21 +226,22 @@ fn main() {
                     rotation -= 1.;
                 }
                 player.obj.rotation += rotation * 2. * u.dt as f32;
-                player.obj.acceleration = impulse * 150. * Vector2::unit_vector(player.obj.rotation);
+                player.thrust = impulse * 150.;
 
                 for planet in planets.iter_mut() {
                     planet.obj.update(u.dt as f32);
                     planet.obj.stay_in_bounds();
                 }
 
-                for player in Some(&mut player) {
-                    player.obj.update(u.dt as f32);
+                for player in Some(&mut player).into_iter().chain(bots.iter_mut().map(|b| &mut b.player)) {
+                    player.update(u.dt as f32);
                     player.obj.stay_in_bounds();
                 }
 
+                lasers.retain(|l| l.ttl > 0.);
                 for laser in lasers.iter_mut() {
                     laser.update(u.dt as f32);
-                    laser.stay_in_bounds();
+                    laser.obj.stay_in_bounds();
                 }
             }
             Event::Input(Input::Close(_)) => {}
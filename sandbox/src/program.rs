@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::SystemTime;
+
+use velox_core::obj::Player;
+use velox_core::control::{ShipController, Sensors, Controls, DebugOutput, load};
+use velox_script::Script;
+
+/// Log lines kept for each program
+const LOG_LINES: usize = 8;
+
+/// Makes a controller out of a `.vxs` script, or anything `velox_core::control::load` takes
+fn load_program(spec: &str) -> Result<Box<dyn ShipController>, String> {
+    if spec.ends_with(".vxs") {
+        let source = fs::read_to_string(spec).map_err(|e| e.to_string())?;
+        match Script::new(&source) {
+            Ok(script) => Ok(Box::new(script)),
+            Err(e) => Err(e.to_string()),
+        }
+    } else {
+        load(spec).map_err(|e| e.to_string())
+    }
+}
+
+/// A ship flown by a program that is loaded again whenever its files change
+pub struct Bot {
+    pub spec: String,
+    pub player: Player,
+    controller: Option<Box<dyn ShipController>>,
+    /// Why loading it failed the last time it was tried
+    load_error: Option<String>,
+    loaded: bool,
+    modified: Option<SystemTime>,
+    /// What the program showed about the last tick, without its log
+    pub debug: DebugOutput,
+    /// Latest lines the program logged, oldest first
+    pub log: VecDeque<String>,
+}
+
+impl Bot {
+    pub fn new(spec: String, player: Player) -> Self {
+        Bot {
+            spec,
+            player,
+            controller: None,
+            load_error: None,
+            loaded: false,
+            modified: None,
+            debug: DebugOutput::default(),
+            log: VecDeque::new(),
+        }
+    }
+    /// When any of the files in the spec last changed
+    fn modified(&self) -> Option<SystemTime> {
+        self.spec.split_whitespace()
+            .filter_map(|word| fs::metadata(word).and_then(|m| m.modified()).ok())
+            .max()
+    }
+    /// Loads the program again if it has changed, keeping the ship as it is
+    ///
+    /// If it doesn't load, the old one keeps flying.
+    pub fn reload_if_changed(&mut self) {
+        let modified = self.modified();
+        if self.loaded && modified == self.modified {
+            return
+        }
+        self.loaded = true;
+        self.modified = modified;
+        match load_program(&self.spec) {
+            Ok(controller) => {
+                println!("Loaded {}", self.spec);
+                self.controller = Some(controller);
+                self.load_error = None;
+            }
+            Err(e) => {
+                println!("{}: {}", self.spec, e);
+                self.load_error = Some(e);
+            }
+        }
+    }
+    pub fn control(&mut self, sensors: &Sensors) -> Controls {
+        let controller = match self.controller {
+            Some(ref mut controller) => controller,
+            None => return Controls::default(),
+        };
+        let controls = controller.control(sensors);
+        self.debug = controller.debug();
+        self.log.extend(self.debug.log.drain(..));
+        while self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+        controls
+    }
+    /// What's wrong with the program, if anything
+    pub fn error(&self) -> Option<String> {
+        match self.load_error {
+            Some(ref e) => Some(e.clone()),
+            None => self.controller.as_ref().and_then(|c| c.error()),
+        }
+    }
+}
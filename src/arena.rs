@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use obj::Player;
+use net::{Idx, TeamId, ModeKind};
+use world::{World, Rules, Event, Input, fit_in, starting_planets};
+use control::{ShipController, Pilot, Controls, Sensors, Radar, sense};
+use replay::{Recorder, Header, REPLAY_VERSION};
+use clock::Clock;
+
+/// Frames between full snapshots in replays
+const KEYFRAME_INTERVAL: u32 = 60;
+
+/// A world where every ship is flown by a controller, without any networking
+pub struct Arena {
+    pub world: World,
+    /// What the match is played as, which is what replays say and what scores are counted by
+    pub mode: ModeKind,
+    pilots: BTreeMap<Idx, Pilot>,
+    /// How long controllers get to answer each tick, every tick waits for all of them if `None`
+    ///
+    /// Ones that get stuck are given up on after `control::STUCK_TIME` and sit still from then on.
+    pub think_time: Option<Duration>,
+    seed: u32,
+    /// Seconds simulated so far
+    pub time: f32,
+    recorder: Option<Recorder>,
+}
+
+impl Arena {
+    pub fn new(rules: Rules, seed: u32) -> Self {
+        let initial = Default::default();
+        let mut world = World::new(rules, seed, initial);
+        world.planets = starting_planets();
+        let mode = if rules.teams > 0 { ModeKind::TeamDeathmatch } else { ModeKind::Deathmatch };
+        Arena {
+            world,
+            mode,
+            pilots: BTreeMap::new(),
+            think_time: None,
+            seed,
+            time: 0.,
+            recorder: None,
+        }
+    }
+    /// Adds a ship that is only flown through `control`
+    pub fn join(&mut self, team: Option<TeamId>) -> Idx {
+        let player = Player {
+            team,
+            .. Player::default()
+        };
+        let i = fit_in(player, &mut self.world.players);
+        self.world.sim.respawn(&mut self.world.players, &self.world.planets, i);
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.input(Input::Join(i, self.world.players[&i]));
+        }
+        i
+    }
+    /// Adds a ship flown by `controller`
+    pub fn add(&mut self, controller: Box<dyn ShipController>, team: Option<TeamId>) -> Idx {
+        let i = self.join(team);
+        self.pilots.insert(i, Pilot::spawn(controller));
+        i
+    }
+    /// What ship `i` can sense right now
+    pub fn sense(&self, i: Idx) -> Sensors {
+        sense(&self.world.players, &self.world.planets, &self.world.lasers, i, self.time)
+    }
+    /// Points ship `i` has in the match's mode
+    pub fn score(&self, i: Idx) -> i32 {
+        self.mode.score(&self.world.players[&i].stats)
+    }
+    /// Makes ship `i` follow `controls` for the next `dt` seconds, if it's alive
+    pub fn control(&mut self, i: Idx, controls: Controls, dt: f32) {
+        let commands = match self.world.players.get(&i) {
+            Some(player) if player.is_alive() => controls.commands(player, dt),
+            _ => return,
+        };
+        for command in commands {
+            let input = Input::Command(i, command);
+            self.world.apply(&input);
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.input(input);
+            }
+        }
+    }
+    /// Records everything from now on to a replay at `path`
+    ///
+    /// Must be called before the first tick so the replay starts with the seed.
+    pub fn record<P: AsRef<Path>>(&mut self, path: P, name: &str) -> io::Result<()> {
+        // Ships joining have drawn from the generator already, playback starts from the seed
+        self.world.sim.reseed(self.seed);
+        let header = Header {
+            version: REPLAY_VERSION,
+            room: name.to_owned(),
+            mode: self.mode,
+            rules: self.world.sim.rules,
+            seed: self.seed,
+            initial: self.world.snapshot(),
+        };
+        self.recorder = Some(Recorder::create(path, &header, KEYFRAME_INTERVAL)?);
+        Ok(())
+    }
+    /// Lets every controller have its say and steps the world `dt` seconds
+    ///
+    /// Waiting for every controller to answer keeps it deterministic.
+    pub fn tick(&mut self, dt: f32) -> Vec<Event> {
+        let alive: Vec<_> = self.pilots.keys().cloned()
+            .filter(|i| self.world.players[i].is_alive())
+            .collect();
+        let radar = Radar::new(&self.world.players, &self.world.planets, &self.world.lasers);
+        for &i in &alive {
+            let sensors = radar.sense(&self.world.players, &self.world.planets, &self.world.lasers, i, self.time);
+            self.pilots.get_mut(&i).unwrap().ask(sensors);
+        }
+        let deadline = self.think_time.map(|t| Instant::now() + t);
+        for &i in &alive {
+            let controls = self.pilots.get_mut(&i).unwrap().answer(deadline);
+            self.control(i, controls, dt);
+        }
+
+        let world = &mut self.world;
+        let events = world.step(dt);
+        self.time += dt;
+        if let Some(ref mut recorder) = self.recorder {
+            let snapshot = || world.snapshot();
+            if let Err(e) = recorder.frame(dt, snapshot) {
+                println!("Couldn't record frame: {}", e);
+                self.recorder = None;
+            }
+        }
+        events
+    }
+    /// Ticks with the time `clock` gives until `duration` seconds have been simulated in all
+    pub fn run(&mut self, duration: f32, clock: &mut Clock) {
+        while self.time < duration {
+            let dt = clock.tick();
+            self.tick(dt);
+        }
+        if let Some(ref mut recorder) = self.recorder {
+            if let Err(e) = recorder.flush() {
+                println!("Couldn't finish recording: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::remove_file;
+    use replay::{Replay, Playback};
+    use control::{bot, STUCK_TIME};
+    use std::thread;
+
+    /// Never answers
+    struct Stuck;
+
+    impl ShipController for Stuck {
+        fn control(&mut self, _: &Sensors) -> Controls {
+            loop {
+                thread::sleep(Duration::from_secs(60));
+            }
+        }
+    }
+
+    #[test]
+    fn stuck_controllers_dont_hold_up_matches() {
+        let mut arena = Arena::new(Rules::default(), 3);
+        let stuck = arena.add(Box::new(Stuck), None);
+        arena.add(bot("gunner").unwrap(), None);
+
+        let start = Instant::now();
+        arena.run(10., &mut Clock::virtual_time(1. / 60.));
+        assert!(start.elapsed() < 2 * STUCK_TIME);
+        assert_eq!(arena.world.players[&stuck].stats.shots_fired, 0);
+    }
+
+    #[test]
+    fn recorded_matches_replay() {
+        let rules = Rules {
+            respawn_time: 1.,
+            .. Rules::default()
+        };
+        let mut arena = Arena::new(rules, 7);
+        arena.add(bot("gunner").unwrap(), None);
+        arena.add(bot("idle").unwrap(), None);
+        let path = temp_dir().join(format!("velox-arena-test-{}.vxr", ::std::process::id()));
+        arena.record(&path, "test").unwrap();
+        arena.run(30., &mut Clock::virtual_time(1. / 60.));
+        let deaths: u32 = arena.world.players.values().map(|p| p.stats.deaths).sum();
+
+        let replay = Replay::load(&path);
+        let _ = remove_file(&path);
+        assert!(deaths > 0, "nobody respawned");
+        let replay = replay.unwrap();
+        assert_eq!(replay.header.mode, arena.mode);
+        assert!(replay.verify().is_ok());
+
+        let mut playback = Playback::new(replay);
+        playback.seek(20.);
+        assert!((playback.time() - 20.).abs() < 0.1);
+        playback.seek(f32::NAN);
+        assert!((playback.time() - 20.).abs() < 0.1);
+        playback.seek(5.);
+        assert!((playback.time() - 5.).abs() < 0.1);
+        playback.seek(f32::INFINITY);
+        assert!(playback.is_finished());
+        playback.seek(f32::NEG_INFINITY);
+        assert_eq!(playback.frame(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+use std::f32::consts::PI;
+
+use obj::{Vect, Vector2, allies};
+use control::{ShipController, Sensors, Contact, Controls};
+use weapon::{weapon, WeaponId, LASER, RAPID_LASER};
+use math::{normalize_angle, intercept_direction, closest_approach};
+
+/// Turn that points the ship towards `angle`, easing off as it gets close to not overshoot
+fn turn_to(s: &Sensors, angle: f32) -> f32 {
+    (3. * normalize_angle(angle - s.rotation)).clamp(-1., 1.)
+}
+
+/// Turn and thrust that bring the ship's velocity towards `vel`
+///
+/// Reverse thrust is used whenever the ship is facing away from where it needs to go.
+fn change_velocity(s: &Sensors, vel: Vect) -> Controls {
+    let dv = vel - s.vel;
+    let need = dv.length();
+    if need < 5. {
+        return Controls::default()
+    }
+    let diff = normalize_angle(dv.direction() - s.rotation);
+    let heading = if diff.abs() > PI / 2. { dv.direction() + PI } else { dv.direction() };
+    Controls {
+        thrust: diff.cos() * (need / 50.).min(1.),
+        turn: turn_to(s, heading),
+        fire: None,
+    }
+}
+
+/// Direction to fire something at `speed` in to hit `target`, if it can be hit at all
+fn aim(s: &Sensors, target: &Contact, speed: f32) -> Option<f32> {
+    intercept_direction(target.pos - s.pos, target.vel - s.vel, speed)
+}
+
+/// Fires `weapon` if it has reloaded, `target` is within its reach and it would hit when fired in
+/// direction `angle`
+fn fire_at(s: &Sensors, target: &Contact, weapon_id: WeaponId, angle: f32) -> Option<WeaponId> {
+    let w = weapon(weapon_id).unwrap();
+    let ready = s.reload[weapon_id as usize] == 0.;
+    let reaches = s.pos.distance_to(target.pos) < 0.9 * w.speed * w.ttl;
+    if ready && reaches && normalize_angle(angle - s.rotation).abs() < 0.05 {
+        Some(weapon_id)
+    } else {
+        None
+    }
+}
+
+/// Turns to aim at `target` and fires `weapon` once the aim is good enough
+fn shoot(s: &Sensors, target: &Contact, weapon_id: WeaponId, controls: &mut Controls) {
+    if let Some(angle) = aim(s, target, weapon(weapon_id).unwrap().speed) {
+        controls.turn = turn_to(s, angle);
+        controls.fire = fire_at(s, target, weapon_id, angle);
+    }
+}
+
+/// Heads for the middle of the map, which the sensors can see all of, when there's nothing in sight
+fn search(s: &Sensors) -> Controls {
+    let to_centre = -s.pos;
+    change_velocity(s, to_centre * (CHASE_SPEED / to_centre.length().max(CHASE_SPEED)))
+}
+
+/// Closest ship that isn't on our team
+fn closest_enemy(s: &Sensors) -> Option<&Contact> {
+    s.ships.iter()
+        .filter(|c| !allies(s.team, c.team))
+        .min_by(|a, b| s.pos.distance_to_squared(a.pos).total_cmp(&s.pos.distance_to_squared(b.pos)))
+}
+
+/// Circles around the closest planet, sidestepping projectiles headed its way
+pub struct Dodger {
+    /// 1 for clockwise, -1 for anticlockwise
+    direction: f32,
+}
+
+/// How far from the planet the dodger orbits
+const ORBIT_RADIUS: f32 = 220.;
+const ORBIT_SPEED: f32 = 140.;
+/// How close a projectile has to pass to be dodged
+const DODGE_DISTANCE: f32 = 50.;
+/// How many seconds ahead the dodger looks for projectiles
+const DODGE_TIME: f32 = 1.5;
+
+impl Default for Dodger {
+    fn default() -> Self {
+        Dodger {
+            direction: 1.,
+        }
+    }
+}
+
+impl ShipController for Dodger {
+    fn control(&mut self, s: &Sensors) -> Controls {
+        let centre = s.planets.iter()
+            .map(|p| p.pos)
+            .min_by(|&a, &b| s.pos.distance_to_squared(a).total_cmp(&s.pos.distance_to_squared(b)))
+            .unwrap_or_default();
+        let out = s.pos - centre;
+        let radius = out.length().max(1.);
+        let out = out / radius;
+        let along = self.direction * Vector2(-out.1, out.0);
+        let mut vel = ORBIT_SPEED * along + (ORBIT_RADIUS - radius).clamp(-100., 100.) * out;
+
+        for p in s.projectiles.iter().filter(|p| p.owner != s.id && !allies(s.team, p.team)) {
+            let d = p.pos - s.pos;
+            let v = p.vel - s.vel;
+            let (t, distance) = closest_approach(d, v);
+            if t <= 0. || t > DODGE_TIME {
+                continue
+            }
+            let miss = d + v * t;
+            if distance < DODGE_DISTANCE {
+                // Get out of the way, going the way it would miss by already
+                let side = if miss.length() > 1. { -miss.normalise() } else { Vector2(-v.1, v.0).normalise() };
+                vel += 2. * ORBIT_SPEED * side;
+                // Turning around the other way makes it harder to lead
+                if side.dot(along) < 0. {
+                    self.direction = -self.direction;
+                }
+            }
+        }
+        change_velocity(s, vel)
+    }
+}
+
+/// Chases the closest enemy and leads its shots with the laser
+pub struct Gunner;
+
+/// How close the gunner likes to get
+const CHASE_DISTANCE: f32 = 250.;
+const CHASE_SPEED: f32 = 160.;
+
+impl ShipController for Gunner {
+    fn control(&mut self, s: &Sensors) -> Controls {
+        let target = match closest_enemy(s) {
+            Some(target) => target,
+            None => return search(s),
+        };
+        let d = target.pos - s.pos;
+        let speed = if d.length() > CHASE_DISTANCE { CHASE_SPEED } else { 0. };
+        let mut controls = change_velocity(s, target.vel + speed * d.normalise());
+        // Aiming matters more than steering once it's close, until then it only takes chances
+        if d.length() < 2. * CHASE_DISTANCE {
+            shoot(s, target, LASER, &mut controls);
+        } else if let Some(angle) = aim(s, target, weapon(LASER).unwrap().speed) {
+            controls.fire = fire_at(s, target, LASER, angle);
+        }
+        controls
+    }
+}
+
+/// Goes after planets that aren't its team's, then after ships once they're all gone
+pub struct Hunter;
+
+/// How close the hunter gets to planets before opening fire with the rapid laser
+const HUNT_DISTANCE: f32 = 300.;
+
+impl ShipController for Hunter {
+    fn control(&mut self, s: &Sensors) -> Controls {
+        let planet = s.planets.iter()
+            .filter(|p| !allies(s.team, p.team))
+            .min_by(|a, b| s.pos.distance_to_squared(a.pos).total_cmp(&s.pos.distance_to_squared(b.pos)));
+        let target = match planet.or_else(|| closest_enemy(s)) {
+            Some(target) => target,
+            None => return search(s),
+        };
+        let d = target.pos - s.pos;
+        let vel = if d.length() > HUNT_DISTANCE { target.vel + CHASE_SPEED * d.normalise() } else { target.vel };
+        let mut controls = change_velocity(s, vel);
+        if d.length() < 1.5 * HUNT_DISTANCE {
+            shoot(s, target, RAPID_LASER, &mut controls);
+        }
+        controls
+    }
+}
+
+/// Keeps its distance from the closest enemy, facing it and backing off with reverse thrust
+pub struct Sniper;
+
+const KITE_MIN: f32 = 450.;
+const KITE_MAX: f32 = 650.;
+
+impl ShipController for Sniper {
+    fn control(&mut self, s: &Sensors) -> Controls {
+        let target = match closest_enemy(s) {
+            Some(target) => target,
+            None => return search(s),
+        };
+        let d = target.pos - s.pos;
+        let distance = d.length();
+        let towards = d.normalise();
+        // Close in or back off along the line between, whichever way the ship is facing
+        let closing = (target.vel - s.vel).dot(towards);
+        let facing = Vector2::unit_vector(s.rotation).dot(towards);
+        let wanted = if distance < KITE_MIN {
+            -100.
+        } else if distance > KITE_MAX {
+            100.
+        } else {
+            0.
+        };
+        let mut controls = Controls {
+            thrust: ((closing + wanted) / 50.).clamp(-1., 1.) * facing,
+            turn: turn_to(s, d.direction()),
+            fire: None,
+        };
+        shoot(s, target, LASER, &mut controls);
+        controls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj::{Player, Planet};
+    use control::{load, sense};
+    use world::{World, Rules, Snapshot, Input};
+
+    const DT: f32 = 1. / 60.;
+
+    /// Flies bot `name` from the middle of the map for `seconds`
+    ///
+    /// It's ship 0, facing right. The `ships` around it sit still.
+    fn fly(name: &str, ships: &[Vect], planets: &[Vect], seconds: f32) -> World {
+        let mut snapshot = Snapshot::default();
+        for (i, &pos) in (0..).zip(Some(Vector2(0., 0.)).iter().chain(ships)) {
+            let mut player = Player::default();
+            player.spawn(pos, 0.);
+            snapshot.players.insert(i, player);
+        }
+        for (i, &pos) in (0..).zip(planets) {
+            snapshot.planets.insert(i, Planet::new(pos.0, pos.1, 0., 0.));
+        }
+        let mut world = World::new(Rules::default(), 0, snapshot);
+        let mut bot = load(name).unwrap();
+
+        let mut time = 0.;
+        while time < seconds {
+            if world.players[&0].is_alive() {
+                let controls = bot.control(&sense(&world.players, &world.planets, &world.lasers, 0, time));
+                for command in controls.commands(&world.players[&0], DT) {
+                    world.apply(&Input::Command(0, command));
+                }
+            }
+            world.step(DT);
+            time += DT;
+        }
+        world
+    }
+    fn distance(world: &World, i: u16) -> f32 {
+        world.players[&0].obj.pos().distance_to(world.players[&i].obj.pos())
+    }
+
+    #[test]
+    fn idle_sits_still() {
+        let world = fly("idle", &[Vector2(200., 0.)], &[], 2.);
+        assert_eq!(world.players[&0].obj.pos(), Vector2(0., 0.));
+        assert_eq!(world.players[&0].stats.shots_fired, 0);
+    }
+
+    #[test]
+    fn gunner_turns_and_shoots_targets() {
+        let world = fly("gunner", &[Vector2(300., 150.)], &[], 5.);
+        assert!(world.players[&0].stats.shots_hit > 0);
+        assert!(world.players[&1].status.health < 10);
+    }
+
+    #[test]
+    fn sniper_backs_off_and_shoots() {
+        let world = fly("sniper", &[Vector2(200., 0.)], &[], 4.);
+        assert!(distance(&world, 1) > 300., "only {} away", distance(&world, 1));
+        assert!(world.players[&0].stats.shots_hit > 0);
+    }
+
+    #[test]
+    fn hunter_goes_after_planets() {
+        let world = fly("hunter", &[], &[Vector2(-500., 300.)], 8.);
+        assert!(world.players[&0].stats.shots_hit > 0);
+        assert!(world.planets.get(&0).is_none_or(|p| p.status.health < 5));
+    }
+
+    #[test]
+    fn dodger_circles_the_closest_planet() {
+        let planet = Vector2(220., 0.);
+        let world = fly("dodger", &[], &[planet, Vector2(-500., 0.)], 6.);
+        let pos = world.players[&0].obj.pos();
+        let radius = pos.distance_to(planet);
+        assert!(100. < radius && radius < 350., "{} from the planet", radius);
+        assert!(world.players[&0].obj.vel().length() > 50.);
+        assert_eq!(world.players[&0].stats.shots_fired, 0);
+    }
+}
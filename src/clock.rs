@@ -0,0 +1,43 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where the time between simulation steps comes from
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    /// Follows the wall clock, pausing a little between ticks
+    Real {
+        last: Instant,
+        pause: Duration,
+    },
+    /// Moves `tick` seconds every tick, as fast as ticks happen
+    Virtual {
+        tick: f32,
+    },
+}
+
+impl Clock {
+    pub fn real(pause: Duration) -> Self {
+        Clock::Real {
+            last: Instant::now(),
+            pause,
+        }
+    }
+    pub fn virtual_time(tick: f32) -> Self {
+        Clock::Virtual {
+            tick,
+        }
+    }
+    /// Waits for the next tick and returns the seconds it covers
+    pub fn tick(&mut self) -> f32 {
+        match *self {
+            Clock::Real{ref mut last, pause} => {
+                thread::sleep(pause);
+                let now = Instant::now();
+                let dur = now - *last;
+                *last = now;
+                dur.as_secs() as f32 + 1e-9 * dur.subsec_nanos() as f32
+            }
+            Clock::Virtual{tick} => tick,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use obj::{Vect, PhysicsObject};
+
+/// Earliest fraction of the way from `start` to `end` at which a point is within `radius` of `centre`
+///
+/// Returns `None` if the segment never gets that close.
+pub fn sweep_point_circle(start: Vect, end: Vect, centre: Vect, radius: f32) -> Option<f32> {
+    let d = end - start;
+    let f = start - centre;
+
+    let c = f.length_squared() - radius * radius;
+    if c <= 0. {
+        return Some(0.);
+    }
+    let a = d.length_squared();
+    if a == 0. {
+        return None;
+    }
+    let b = 2. * f.dot(d);
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / (2. * a);
+    if (0. ..=1.).contains(&t) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Time into the next `dt` seconds at which `projectile` comes within `radius` of `target`
+///
+/// Both objects are moved as `PhysicsObject::update` would move them, so nothing tunnels
+/// through no matter how long the tick is.
+pub fn sweep(projectile: &PhysicsObject, target: &PhysicsObject, radius: f32, dt: f32) -> Option<f32> {
+    let start = projectile.pos() - target.pos();
+    let end = start + projectile.displacement(dt) - target.displacement(dt);
+    sweep_point_circle(start, end, Vect::default(), radius).map(|t| t * dt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj::Vector2;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn points_passing_through_hit() {
+        let t = sweep_point_circle(Vector2(-100., 0.), Vector2(100., 0.), Vector2(0., 0.), 10.).unwrap();
+        assert!(close(t, 0.45));
+
+        // Neither end is anywhere near the target, but it flies straight through in one tick
+        let projectile = PhysicsObject::new(-100., 0., 2000., 0.);
+        let target = PhysicsObject::new(0., 0., 0., 0.);
+        assert!(close(sweep(&projectile, &target, 10., 0.1).unwrap(), 0.045));
+
+        // Closing in from both sides meets in the middle
+        let target = PhysicsObject::new(100., 0., -2000., 0.);
+        assert!(close(sweep(&projectile, &target, 10., 0.1).unwrap(), 0.0475));
+    }
+
+    #[test]
+    fn near_misses_miss() {
+        assert_eq!(sweep_point_circle(Vector2(-100., 11.), Vector2(100., 11.), Vector2(0., 0.), 10.), None);
+        // Stopping short
+        assert_eq!(sweep_point_circle(Vector2(-100., 0.), Vector2(-11., 0.), Vector2(0., 0.), 10.), None);
+        // Having already flown past
+        assert_eq!(sweep_point_circle(Vector2(11., 0.), Vector2(100., 0.), Vector2(0., 0.), 10.), None);
+    }
+
+    #[test]
+    fn starting_inside_hits_at_once() {
+        assert_eq!(sweep_point_circle(Vector2(5., 0.), Vector2(100., 0.), Vector2(0., 0.), 10.), Some(0.));
+        assert_eq!(sweep_point_circle(Vector2(5., 0.), Vector2(5., 0.), Vector2(0., 0.), 10.), Some(0.));
+    }
+
+    #[test]
+    fn moving_together_never_closes_in() {
+        let projectile = PhysicsObject::new(-100., 0., 500., 300.);
+        let target = PhysicsObject::new(0., 0., 500., 300.);
+        assert_eq!(sweep(&projectile, &target, 10., 1.), None);
+
+        let target = PhysicsObject::new(-95., 0., 500., 300.);
+        assert_eq!(sweep(&projectile, &target, 10., 1.), Some(0.));
+    }
+}
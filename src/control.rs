@@ -0,0 +1,599 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::io;
+
+use obj::{Vect, Planet, Player, Laser};
+use net::{Idx, TeamId, Target};
+use weapon::{WeaponId, WEAPON_COUNT};
+use damage::{Status, System};
+use grid::SpatialGrid;
+use world::Command;
+use process::Program;
+use bots::{Dodger, Gunner, Hunter, Sniper};
+#[cfg(feature = "wasm")]
+use wasm::WasmProgram;
+
+/// Fastest a ship can accelerate
+pub const MAX_THRUST: f32 = 150.;
+/// Fastest a ship can turn, in radians per second
+pub const TURN_RATE: f32 = 2.;
+
+/// Another ship or a planet as seen by the sensors
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    pub id: Idx,
+    pub team: Option<TeamId>,
+    pub pos: Vect,
+    pub vel: Vect,
+    pub rotation: f32,
+}
+
+/// A projectile as seen by the sensors
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Projectile {
+    pub id: Idx,
+    pub owner: Idx,
+    pub team: Option<TeamId>,
+    pub weapon: WeaponId,
+    pub pos: Vect,
+    pub vel: Vect,
+}
+
+/// Everything a ship knows about itself and its surroundings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sensors {
+    /// Seconds since the match started
+    pub time: f32,
+    pub id: Idx,
+    pub team: Option<TeamId>,
+    pub alive: bool,
+    pub pos: Vect,
+    pub vel: Vect,
+    pub rotation: f32,
+    pub status: Status,
+    /// Seconds left before each weapon can fire again
+    pub reload: [f32; WEAPON_COUNT],
+    /// Every other living ship within `SENSOR_RANGE`, empty while the sensors are knocked out
+    pub ships: Vec<Contact>,
+    /// Within `SENSOR_RANGE`, empty while the sensors are knocked out
+    pub planets: Vec<Contact>,
+    /// Within `SENSOR_RANGE`, empty while the sensors are knocked out
+    pub projectiles: Vec<Projectile>,
+}
+
+/// How a reading is best handed to a ship program
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reading {
+    Number,
+    /// Ids, counts, teams and booleans
+    Whole,
+}
+
+/// Every reading `Sensors::get` knows, whether it takes an index and how it's best handed over
+pub const READINGS: &[(&str, bool, Reading)] = &[
+    ("time", false, Reading::Number),
+    ("id", false, Reading::Whole),
+    ("team", false, Reading::Whole),
+    ("alive", false, Reading::Whole),
+    ("x", false, Reading::Number),
+    ("y", false, Reading::Number),
+    ("vx", false, Reading::Number),
+    ("vy", false, Reading::Number),
+    ("rotation", false, Reading::Number),
+    ("health", false, Reading::Whole),
+    ("shield", false, Reading::Number),
+    ("reload", true, Reading::Number),
+    ("works", true, Reading::Whole),
+    ("ship_count", false, Reading::Whole),
+    ("ship_id", true, Reading::Whole),
+    ("ship_team", true, Reading::Whole),
+    ("ship_x", true, Reading::Number),
+    ("ship_y", true, Reading::Number),
+    ("ship_vx", true, Reading::Number),
+    ("ship_vy", true, Reading::Number),
+    ("ship_rotation", true, Reading::Number),
+    ("planet_count", false, Reading::Whole),
+    ("planet_x", true, Reading::Number),
+    ("planet_y", true, Reading::Number),
+    ("planet_vx", true, Reading::Number),
+    ("planet_vy", true, Reading::Number),
+    ("projectile_count", false, Reading::Whole),
+    ("projectile_owner", true, Reading::Whole),
+    ("projectile_weapon", true, Reading::Whole),
+    ("projectile_x", true, Reading::Number),
+    ("projectile_y", true, Reading::Number),
+    ("projectile_vx", true, Reading::Number),
+    ("projectile_vy", true, Reading::Number),
+];
+
+impl Sensors {
+    /// Reads `name` the way ship programs see it, `None` if there's no such reading
+    ///
+    /// Indexed readings take `i` in their list, out of range ones read as 0. Teams are -1 for
+    /// none and booleans are 0 or 1.
+    pub fn get(&self, name: &str, i: usize) -> Option<f32> {
+        let team = |t: Option<TeamId>| t.map_or(-1., f32::from);
+        let truth = |b: bool| if b { 1. } else { 0. };
+        Some(match name {
+            "time" => self.time,
+            "id" => self.id as f32,
+            "team" => team(self.team),
+            "alive" => truth(self.alive),
+            "x" => self.pos.0,
+            "y" => self.pos.1,
+            "vx" => self.vel.0,
+            "vy" => self.vel.1,
+            "rotation" => self.rotation,
+            "health" => self.status.health as f32,
+            "shield" => self.status.shield,
+            "reload" => self.reload.get(i).cloned().unwrap_or(0.),
+            "works" => truth(System::from_index(i).is_some_and(|s| self.status.works(s))),
+            "ship_count" => self.ships.len() as f32,
+            "ship_id" => self.ships.get(i).map_or(0., |c| c.id as f32),
+            "ship_team" => self.ships.get(i).map_or(-1., |c| team(c.team)),
+            "ship_x" => self.ships.get(i).map_or(0., |c| c.pos.0),
+            "ship_y" => self.ships.get(i).map_or(0., |c| c.pos.1),
+            "ship_vx" => self.ships.get(i).map_or(0., |c| c.vel.0),
+            "ship_vy" => self.ships.get(i).map_or(0., |c| c.vel.1),
+            "ship_rotation" => self.ships.get(i).map_or(0., |c| c.rotation),
+            "planet_count" => self.planets.len() as f32,
+            "planet_x" => self.planets.get(i).map_or(0., |c| c.pos.0),
+            "planet_y" => self.planets.get(i).map_or(0., |c| c.pos.1),
+            "planet_vx" => self.planets.get(i).map_or(0., |c| c.vel.0),
+            "planet_vy" => self.planets.get(i).map_or(0., |c| c.vel.1),
+            "projectile_count" => self.projectiles.len() as f32,
+            "projectile_owner" => self.projectiles.get(i).map_or(0., |p| p.owner as f32),
+            "projectile_weapon" => self.projectiles.get(i).map_or(0., |p| p.weapon as f32),
+            "projectile_x" => self.projectiles.get(i).map_or(0., |p| p.pos.0),
+            "projectile_y" => self.projectiles.get(i).map_or(0., |p| p.pos.1),
+            "projectile_vx" => self.projectiles.get(i).map_or(0., |p| p.vel.0),
+            "projectile_vy" => self.projectiles.get(i).map_or(0., |p| p.vel.1),
+            _ => return None,
+        })
+    }
+}
+
+/// How far the sensors reach, as far as a laser flies
+pub const SENSOR_RANGE: f32 = 1000.;
+
+/// Where everything is, sorted into grids so each ship only goes through what's within range
+///
+/// Made once a tick to sense for every ship.
+pub struct Radar {
+    bodies: SpatialGrid<Target>,
+    projectiles: SpatialGrid<Idx>,
+}
+
+impl Radar {
+    pub fn new(players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, lasers: &BTreeMap<Idx, Laser>) -> Self {
+        let mut bodies = SpatialGrid::new(SENSOR_RANGE / 4.);
+        for (&i, planet) in planets.iter() {
+            bodies.insert(Target::Planet(i), planet.obj.pos());
+        }
+        for (&i, player) in players.iter().filter(|&(_, p)| p.is_alive()) {
+            bodies.insert(Target::Player(i), player.obj.pos());
+        }
+        let mut projectiles = SpatialGrid::new(SENSOR_RANGE / 4.);
+        for (&i, laser) in lasers.iter() {
+            projectiles.insert(i, laser.obj.pos());
+        }
+        Radar {
+            bodies,
+            projectiles,
+        }
+    }
+    /// What ship `id` can sense, ships, planets and projectiles come in order of their index
+    pub fn sense(&self, players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, lasers: &BTreeMap<Idx, Laser>, id: Idx, time: f32) -> Sensors {
+        let me = &players[&id];
+        let pos = me.obj.pos();
+        let blind = !me.status.works(System::Sensors);
+
+        let (mut ships, mut planet_contacts, mut projectiles) = (Vec::new(), Vec::new(), Vec::new());
+        if !blind {
+            self.bodies.query(pos, SENSOR_RANGE, |target, _| match target {
+                Target::Player(i) if i != id => {
+                    let p = &players[&i];
+                    ships.push(Contact {
+                        id: i,
+                        team: p.team,
+                        pos: p.obj.pos(),
+                        vel: p.obj.vel(),
+                        rotation: p.obj.rotation,
+                    });
+                }
+                Target::Player(_) => (),
+                Target::Planet(i) => {
+                    let p = &planets[&i];
+                    planet_contacts.push(Contact {
+                        id: i,
+                        team: p.team,
+                        pos: p.obj.pos(),
+                        vel: p.obj.vel(),
+                        rotation: 0.,
+                    });
+                }
+            });
+            self.projectiles.query(pos, SENSOR_RANGE, |i, _| {
+                let l = &lasers[&i];
+                projectiles.push(Projectile {
+                    id: i,
+                    owner: l.owner,
+                    team: l.team,
+                    weapon: l.weapon,
+                    pos: l.obj.pos(),
+                    vel: l.obj.vel(),
+                });
+            });
+            ships.sort_by_key(|c| c.id);
+            planet_contacts.sort_by_key(|c| c.id);
+            projectiles.sort_by_key(|p| p.id);
+        }
+
+        Sensors {
+            time,
+            id,
+            team: me.team,
+            alive: me.is_alive(),
+            pos,
+            vel: me.obj.vel(),
+            rotation: me.obj.rotation,
+            status: me.status,
+            reload: me.reload,
+            ships,
+            planets: planet_contacts,
+            projectiles,
+        }
+    }
+}
+
+/// What ship `id` can sense, when it's the only one sensing
+pub fn sense(players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, lasers: &BTreeMap<Idx, Laser>, id: Idx, time: f32) -> Sensors {
+    Radar::new(players, planets, lasers).sense(players, planets, lasers, id, time)
+}
+
+/// What a ship is told to do for one tick
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct Controls {
+    /// From -1 for full reverse to 1 for full ahead
+    pub thrust: f32,
+    /// From -1 for turning anticlockwise as fast as possible to 1 for clockwise
+    pub turn: f32,
+    pub fire: Option<WeaponId>,
+}
+
+/// Clamps `x` between -1 and 1, treating nonsense like NaN as 0
+fn unit(x: f32) -> f32 {
+    if x.is_nan() {
+        0.
+    } else {
+        x.clamp(-1., 1.)
+    }
+}
+
+impl Controls {
+    /// Commands that make `player` do this for the next `dt` seconds
+    pub fn commands(&self, player: &Player, dt: f32) -> Vec<Command> {
+        let mut commands = Vec::new();
+        let thrust = MAX_THRUST * unit(self.thrust);
+        if thrust != player.thrust {
+            commands.push(Command::Thrust(thrust));
+        }
+        let turn = unit(self.turn);
+        if turn != 0. {
+            commands.push(Command::Rotate(TURN_RATE * dt * turn));
+        }
+        if let Some(weapon) = self.fire {
+            commands.push(Command::Shoot(weapon));
+        }
+        commands
+    }
+}
+
+/// Most log lines a ship program can write in one tick
+pub const MAX_LOG_LINES: usize = 16;
+/// Most debug shapes a ship program can draw in one tick
+pub const MAX_SHAPES: usize = 256;
+
+/// Something a ship program draws to show what it's thinking, in world coordinates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Shape {
+    Line {
+        from: Vect,
+        to: Vect,
+    },
+    Circle {
+        pos: Vect,
+        radius: f32,
+    },
+    Text {
+        pos: Vect,
+        text: String,
+    },
+}
+
+/// What a ship program wants to show about one tick, for working out why it does what it does
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct DebugOutput {
+    pub log: Vec<String>,
+    /// Numbers worth keeping an eye on by name
+    pub values: BTreeMap<String, f32>,
+    pub shapes: Vec<Shape>,
+}
+
+impl DebugOutput {
+    pub fn log(&mut self, line: String) {
+        if self.log.len() < MAX_LOG_LINES {
+            self.log.push(line);
+        }
+    }
+    pub fn value(&mut self, name: String, value: f32) {
+        self.values.insert(name, value);
+    }
+    pub fn shape(&mut self, shape: Shape) {
+        if self.shapes.len() < MAX_SHAPES {
+            self.shapes.push(shape);
+        }
+    }
+    /// Drops anything past the limits, for output that didn't go through the methods above
+    pub fn truncate(&mut self) {
+        self.log.truncate(MAX_LOG_LINES);
+        self.shapes.truncate(MAX_SHAPES);
+    }
+    /// Takes it apart to be sent over the network
+    pub fn into_pieces(self) -> Vec<DebugPiece> {
+        let log = self.log.into_iter().map(DebugPiece::Log);
+        let values = self.values.into_iter().map(|(name, v)| DebugPiece::Value(name, v));
+        let shapes = self.shapes.into_iter().map(|shape| match shape {
+            Shape::Line{from, to} => DebugPiece::Line(from, to),
+            Shape::Circle{pos, radius} => DebugPiece::Circle(pos, radius),
+            Shape::Text{pos, text} => DebugPiece::Text(pos, text),
+        });
+        log.chain(values).chain(shapes).collect()
+    }
+    /// Puts back a piece that came over the network
+    pub fn add(&mut self, piece: DebugPiece) {
+        match piece {
+            DebugPiece::Log(line) => self.log(line),
+            DebugPiece::Value(name, v) => self.value(name, v),
+            DebugPiece::Line(from, to) => self.shape(Shape::Line{from, to}),
+            DebugPiece::Circle(pos, radius) => self.shape(Shape::Circle{pos, radius}),
+            DebugPiece::Text(pos, text) => self.shape(Shape::Text{pos, text}),
+        }
+    }
+}
+
+/// One log line, value or shape of a `DebugOutput`
+///
+/// Shapes are tagged for ship programs' JSON in a way the network's encoding can't read, so
+/// they're sent like this instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DebugPiece {
+    Log(String),
+    Value(String, f32),
+    Line(Vect, Vect),
+    Circle(Vect, f32),
+    Text(Vect, String),
+}
+
+/// Something that flies a ship, like a bot
+pub trait ShipController: Send {
+    /// Decides what to do this tick
+    fn control(&mut self, sensors: &Sensors) -> Controls;
+    /// What went wrong during the last tick, if anything
+    fn error(&self) -> Option<String> {
+        None
+    }
+    /// Takes what the controller wants to show about the last tick
+    fn debug(&mut self) -> DebugOutput {
+        DebugOutput::default()
+    }
+    /// Whether it has stopped working for good, doing nothing from then on
+    fn has_crashed(&self) -> bool {
+        false
+    }
+}
+
+/// Sits still and does nothing
+pub struct Idle;
+
+impl ShipController for Idle {
+    fn control(&mut self, _: &Sensors) -> Controls {
+        Controls::default()
+    }
+}
+
+/// Names of every built-in bot
+pub const BOTS: [&str; 5] = ["idle", "dodger", "gunner", "hunter", "sniper"];
+
+/// Makes a fresh controller for the built-in bot called `name`
+pub fn bot(name: &str) -> Option<Box<dyn ShipController>> {
+    Some(match name {
+        "idle" => Box::new(Idle),
+        "dodger" => Box::new(Dodger::default()),
+        "gunner" => Box::new(Gunner),
+        "hunter" => Box::new(Hunter),
+        "sniper" => Box::new(Sniper),
+        _ => return None,
+    })
+}
+
+/// How long ship programs get to answer each tick
+pub const PROGRAM_TIME_LIMIT: Duration = Duration::from_millis(50);
+
+/// Makes a controller out of a built-in bot's name, a `.wasm` file or else a command running a ship program
+pub fn load(spec: &str) -> io::Result<Box<dyn ShipController>> {
+    if let Some(bot) = bot(spec) {
+        return Ok(bot)
+    }
+    if spec.ends_with(".wasm") {
+        return load_wasm(spec)
+    }
+    Ok(Box::new(Program::spawn(spec, PROGRAM_TIME_LIMIT)?))
+}
+
+#[cfg(feature = "wasm")]
+fn load_wasm(path: &str) -> io::Result<Box<dyn ShipController>> {
+    let wasm = ::std::fs::read(path)?;
+    match WasmProgram::new(&wasm) {
+        Ok(program) => Ok(Box::new(program)),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn load_wasm(_: &str) -> io::Result<Box<dyn ShipController>> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "built without WebAssembly support"))
+}
+
+/// How long a controller can take over one tick before it's taken to be stuck for good
+pub const STUCK_TIME: Duration = Duration::from_secs(1);
+
+/// A controller running on its own thread, so a slow one can't hold up the simulation
+///
+/// Controls and debug output come back, errors are left behind. One that takes longer
+/// than `STUCK_TIME` to answer counts as crashed, its thread is left to itself.
+pub struct Pilot {
+    sensors: Sender<Sensors>,
+    controls: Receiver<(Controls, DebugOutput)>,
+    last: Controls,
+    /// What the controller showed about the last tick it answered for, until it's taken
+    debug: Option<DebugOutput>,
+    thinking: bool,
+    /// When it was handed the readings it's thinking about
+    asked: Instant,
+    crashed: bool,
+}
+
+impl Pilot {
+    pub fn spawn(mut controller: Box<dyn ShipController>) -> Self {
+        let (sensors_tx, sensors_rx) = channel::<Sensors>();
+        let (controls_tx, controls_rx) = channel();
+        thread::spawn(move || {
+            for sensors in sensors_rx {
+                let controls = controller.control(&sensors);
+                if controls_tx.send((controls, controller.debug())).is_err() || controller.has_crashed() {
+                    break
+                }
+            }
+        });
+
+        Pilot {
+            sensors: sensors_tx,
+            controls: controls_rx,
+            last: Controls::default(),
+            debug: None,
+            thinking: false,
+            asked: Instant::now(),
+            crashed: false,
+        }
+    }
+    /// Whether the controller panicked, crashed or got stuck, it does nothing from then on
+    pub fn has_crashed(&self) -> bool {
+        self.crashed
+    }
+    /// Takes what the controller showed about the last tick it answered for, if it hasn't been already
+    pub fn debug(&mut self) -> Option<DebugOutput> {
+        self.debug.take()
+    }
+    /// Hands the controller new readings, unless it's still busy with the last ones
+    pub fn ask(&mut self, sensors: Sensors) {
+        if self.thinking || self.crashed {
+            return
+        }
+        self.thinking = self.sensors.send(sensors).is_ok();
+        self.crashed = !self.thinking;
+        self.asked = Instant::now();
+    }
+    /// Waits for an answer until `deadline`, or without one until it's been thinking for `STUCK_TIME`
+    ///
+    /// Without an answer in time, the ship keeps doing what it was told last, minus firing.
+    pub fn answer(&mut self, deadline: Option<Instant>) -> Controls {
+        if self.thinking {
+            let stuck = self.asked + STUCK_TIME;
+            let deadline = deadline.map(|d| d.min(stuck)).unwrap_or(stuck);
+            match self.controls.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((controls, debug)) => {
+                    self.thinking = false;
+                    self.last = controls;
+                    self.debug = Some(debug);
+                    return controls
+                }
+                Err(RecvTimeoutError::Timeout) if Instant::now() < stuck => (),
+                Err(_) => {
+                    self.thinking = false;
+                    self.crashed = true;
+                    self.last = Controls::default();
+                }
+            }
+        }
+        Controls {
+            fire: None,
+            .. self.last
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Never answers
+    struct Stuck;
+
+    impl ShipController for Stuck {
+        fn control(&mut self, _: &Sensors) -> Controls {
+            loop {
+                thread::sleep(Duration::from_secs(60));
+            }
+        }
+    }
+
+    fn sensors() -> Sensors {
+        let mut players = BTreeMap::new();
+        players.insert(0, Player::default());
+        sense(&players, &BTreeMap::new(), &BTreeMap::new(), 0, 0.)
+    }
+
+    #[test]
+    fn every_reading_can_be_read() {
+        let sensors = sensors();
+        for &(name, _, _) in READINGS {
+            assert!(sensors.get(name, 0).is_some(), "{} can't be read", name);
+        }
+        assert_eq!(sensors.get("ship_team", 5), Some(-1.));
+        assert_eq!(sensors.get("works", 1), Some(1.));
+        assert_eq!(sensors.get("works", 3), Some(0.));
+        assert_eq!(sensors.get("fuel", 0), None);
+    }
+
+    #[test]
+    fn stuck_pilots_crash() {
+        let mut pilot = Pilot::spawn(Box::new(Stuck));
+        pilot.ask(sensors());
+        // Short deadlines are just missed
+        assert_eq!(pilot.answer(Some(Instant::now() + Duration::from_millis(10))), Controls::default());
+        assert!(!pilot.has_crashed());
+
+        // Waiting without one gives up in the end
+        let start = Instant::now();
+        assert_eq!(pilot.answer(None), Controls::default());
+        assert!(pilot.has_crashed());
+        assert!(start.elapsed() < 2 * STUCK_TIME);
+
+        pilot.ask(sensors());
+        assert_eq!(pilot.answer(None), Controls::default());
+    }
+
+    #[test]
+    fn pilots_answer_for_their_controllers() {
+        let mut pilot = Pilot::spawn(bot("gunner").unwrap());
+        pilot.ask(sensors());
+        pilot.answer(None);
+        assert!(!pilot.has_crashed());
+    }
+}
@@ -0,0 +1,190 @@
+use weapon::Weapon;
+
+/// A part of a ship that can be knocked out by hits
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum System {
+    Sensors,
+    Engines,
+    Weapons,
+}
+
+pub const SYSTEM_COUNT: usize = 3;
+
+impl System {
+    /// The system numbered `i` in the order above, as ship programs name them
+    pub fn from_index(i: usize) -> Option<System> {
+        match i {
+            0 => Some(System::Sensors),
+            1 => Some(System::Engines),
+            2 => Some(System::Weapons),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Status {
+    pub health: u8,
+    pub max_health: u8,
+    pub shield: f32,
+    pub max_shield: f32,
+    /// Shield regained per second
+    pub shield_regen: f32,
+    /// Seconds after a hit before the shield starts regenerating
+    pub regen_delay: f32,
+    /// Subtracted from any damage that gets through the shield
+    pub armor: u8,
+    /// Seconds left before each system works again
+    pub disabled: [f32; SYSTEM_COUNT],
+    since_hit: f32,
+}
+
+impl Status {
+    pub fn ship() -> Self {
+        Status {
+            health: 10,
+            max_health: 10,
+            shield: 4.,
+            max_shield: 4.,
+            shield_regen: 1.,
+            regen_delay: 3.,
+            armor: 0,
+            disabled: [0.; SYSTEM_COUNT],
+            since_hit: 0.,
+        }
+    }
+    pub fn planet() -> Self {
+        Status {
+            health: 5,
+            max_health: 5,
+            shield: 0.,
+            max_shield: 0.,
+            shield_regen: 0.,
+            regen_delay: 0.,
+            armor: 0,
+            disabled: [0.; SYSTEM_COUNT],
+            since_hit: 0.,
+        }
+    }
+    #[inline]
+    pub fn is_dead(&self) -> bool {
+        self.health == 0
+    }
+    #[inline]
+    pub fn works(&self, system: System) -> bool {
+        self.disabled[system as usize] <= 0.
+    }
+    /// Takes `damage` from `weapon`, shield first
+    ///
+    /// Returns the damage done to the hull after armor, no more than the health it had left.
+    pub fn hit(&mut self, damage: u8, weapon: &Weapon) -> u8 {
+        self.since_hit = 0.;
+
+        let absorbed = self.shield.min(damage as f32);
+        self.shield -= absorbed;
+        let hull = ((damage as f32 - absorbed).ceil() as u8).saturating_sub(self.armor);
+        let done = hull.min(self.health);
+
+        if hull > 0 {
+            self.health -= done;
+            if let Some((system, time)) = weapon.disables {
+                let disabled = &mut self.disabled[system as usize];
+                *disabled = disabled.max(time);
+            }
+        }
+        done
+    }
+    /// Whether the health, shield or which systems work differ from `other`
+    ///
+    /// Timers counting down don't count until they run out.
+    pub fn noticeably_differs(&self, other: &Status) -> bool {
+        self.health != other.health || self.shield != other.shield
+            || [System::Sensors, System::Engines, System::Weapons].iter().any(|&s| self.works(s) != other.works(s))
+    }
+    pub fn update(&mut self, dt: f32) {
+        for disabled in self.disabled.iter_mut() {
+            *disabled = (*disabled - dt).max(0.);
+        }
+        self.since_hit += dt;
+        if self.since_hit >= self.regen_delay {
+            self.shield = (self.shield + self.shield_regen * dt).min(self.max_shield);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weapon::{WEAPONS, RAPID_LASER, MISSILE, MINE};
+
+    #[test]
+    fn shields_take_hits_first() {
+        let mut status = Status::ship();
+        assert_eq!(status.hit(3, &WEAPONS[MISSILE as usize]), 0);
+        assert_eq!(status.shield, 1.);
+        assert_eq!(status.health, 10);
+        assert!(status.works(System::Engines));
+
+        // What the shield can't take goes through to the hull
+        assert_eq!(status.hit(3, &WEAPONS[MISSILE as usize]), 2);
+        assert_eq!(status.shield, 0.);
+        assert_eq!(status.health, 8);
+
+        // Partly used up shields don't round damage away
+        let mut status = Status::ship();
+        status.shield = 0.5;
+        assert_eq!(status.hit(2, &WEAPONS[RAPID_LASER as usize]), 2);
+        assert_eq!(status.health, 8);
+
+        // Only the health it had left counts
+        assert_eq!(status.hit(20, &WEAPONS[RAPID_LASER as usize]), 8);
+        assert!(status.is_dead());
+        assert_eq!(status.hit(20, &WEAPONS[RAPID_LASER as usize]), 0);
+    }
+
+    #[test]
+    fn armor_reduces_hull_damage() {
+        let mut status = Status::planet();
+        status.armor = 1;
+        assert_eq!(status.hit(3, &WEAPONS[MISSILE as usize]), 2);
+        assert_eq!(status.health, 3);
+        assert!(!status.works(System::Engines));
+
+        let mut status = Status::planet();
+        status.armor = 1;
+        assert_eq!(status.hit(1, &WEAPONS[MISSILE as usize]), 0);
+        assert_eq!(status.health, 5);
+        // Nothing got through, so nothing is knocked out
+        assert!(status.works(System::Engines));
+    }
+
+    #[test]
+    fn disabled_systems_come_back() {
+        let mut status = Status::planet();
+        status.hit(1, &WEAPONS[MINE as usize]);
+        // A shorter knock out doesn't cut the longer one short
+        status.hit(1, &WEAPONS[MISSILE as usize]);
+        assert_eq!(status.disabled[System::Engines as usize], 3.);
+
+        status.update(2.5);
+        assert!(!status.works(System::Engines));
+        assert!(status.works(System::Sensors));
+        assert!(status.works(System::Weapons));
+        status.update(0.5);
+        assert!(status.works(System::Engines));
+        status.update(1.);
+        assert_eq!(status.disabled, [0.; SYSTEM_COUNT]);
+    }
+
+    #[test]
+    fn shields_regenerate_after_a_while() {
+        let mut status = Status::ship();
+        status.hit(4, &WEAPONS[RAPID_LASER as usize]);
+        status.update(2.);
+        assert_eq!(status.shield, 0.);
+        status.update(1.);
+        assert_eq!(status.shield, 1.);
+        status.update(10.);
+        assert_eq!(status.shield, status.max_shield);
+    }
+}
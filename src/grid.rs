@@ -0,0 +1,179 @@
+use obj::{Vect, Vector2};
+
+use std::collections::HashMap;
+
+/// Uniform grid for broadphase queries.
+///
+/// Meant to be cleared and refilled every tick.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(T, Vect)>>,
+}
+
+impl<T: Copy> SpatialGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+    #[inline]
+    fn cell(&self, p: Vect) -> (i32, i32) {
+        ((p.0 / self.cell_size).floor() as i32, (p.1 / self.cell_size).floor() as i32)
+    }
+    /// Removes all items but keeps the allocated cells around for reuse
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+    pub fn insert(&mut self, item: T, pos: Vect) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push((item, pos));
+    }
+    /// Calls `f` with every item within `radius` of `centre`
+    pub fn query<F>(&self, centre: Vect, radius: f32, mut f: F)
+    where F: FnMut(T, Vect) {
+        let r = radius * radius;
+        self.query_cells(centre, radius, |item, pos| if centre.distance_to_squared(pos) < r {
+            f(item, pos)
+        })
+    }
+    /// Calls `f` with every item in a cell overlapping the square around `centre`
+    ///
+    /// Items may be further away than `radius`, so this is for when the caller does its own narrowphase.
+    pub fn query_cells<F>(&self, centre: Vect, radius: f32, mut f: F)
+    where F: FnMut(T, Vect) {
+        let (x0, y0) = self.cell(centre - Vector2(radius, radius));
+        let (x1, y1) = self.cell(centre + Vector2(radius, radius));
+
+        for x in x0..x1+1 {
+            for y in y0..y1+1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    for &(item, pos) in cell {
+                        f(item, pos)
+                    }
+                }
+            }
+        }
+    }
+    /// Collects every item within `radius` of `centre`
+    pub fn within(&self, centre: Vect, radius: f32) -> Vec<T> {
+        let mut v = Vec::new();
+        self.query(centre, radius, |item, _| v.push(item));
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use obj::{PhysicsObject, random_position};
+    use world::seeded_rng;
+
+    const CELL: f32 = 100.;
+
+    fn sorted(mut v: Vec<usize>) -> Vec<usize> {
+        v.sort();
+        v
+    }
+    /// Every item within `radius` of `centre` found the slow way
+    fn brute_force(items: &[Vect], centre: Vect, radius: f32) -> Vec<usize> {
+        (0..items.len()).filter(|&i| centre.distance_to_squared(items[i]) < radius * radius).collect()
+    }
+    fn grid(items: &[Vect]) -> SpatialGrid<usize> {
+        let mut grid = SpatialGrid::new(CELL);
+        for (i, &pos) in items.iter().enumerate() {
+            grid.insert(i, pos);
+        }
+        grid
+    }
+
+    #[test]
+    fn queries_find_what_a_full_scan_finds() {
+        let mut rng = seeded_rng(26);
+        let items: Vec<_> = (0..500).map(|_| random_position(&mut rng)).collect();
+        let grid = grid(&items);
+
+        for _ in 0..200 {
+            let centre = random_position(&mut rng);
+            let radius = rng.gen_range(1., 3. * CELL);
+            assert_eq!(sorted(grid.within(centre, radius)), brute_force(&items, centre, radius));
+
+            let mut cells = Vec::new();
+            grid.query_cells(centre, radius, |i, _| cells.push(i));
+            let cells = sorted(cells);
+            for i in brute_force(&items, centre, radius) {
+                assert!(cells.binary_search(&i).is_ok(), "{} is in range but not in the cells", i);
+            }
+        }
+    }
+
+    #[test]
+    fn items_on_cell_edges_are_found_from_both_sides() {
+        // On the edges of the cells around the origin, where the sign of the coordinates flips
+        let items = vec![
+            Vector2(0., 0.), Vector2(CELL, 0.), Vector2(-CELL, 0.), Vector2(0., -CELL),
+            Vector2(-0.01, -0.01), Vector2(CELL - 0.01, CELL), Vector2(-CELL, CELL + 0.01),
+        ];
+        let grid = grid(&items);
+
+        for &centre in &[Vector2(-5., -5.), Vector2(5., 5.), Vector2(CELL + 5., 0.), Vector2(-CELL - 5., 0.), Vector2(0., -CELL - 5.)] {
+            for &radius in &[1., 10., CELL, 1.5 * CELL] {
+                assert_eq!(sorted(grid.within(centre, radius)), brute_force(&items, centre, radius));
+            }
+        }
+    }
+
+    #[test]
+    fn items_in_neighbouring_cells_are_found() {
+        // One just over the edge into each of the eight cells around the middle one
+        let centre = Vector2(CELL / 2., CELL / 2.);
+        let mut items = Vec::new();
+        for &dx in &[-1., 0., 1.] {
+            for &dy in &[-1., 0., 1.] {
+                items.push(centre + Vector2(dx, dy) * (CELL / 2. + 1.));
+            }
+        }
+        let grid = grid(&items);
+
+        assert_eq!(sorted(grid.within(centre, CELL)), (0..9).collect::<Vec<_>>());
+        assert_eq!(grid.within(centre, 10.), vec![4]);
+    }
+
+    #[test]
+    fn wrapped_around_items_are_found_where_they_ended_up() {
+        // Flying off either edge of the map puts them at the other one
+        let mut objects = [
+            PhysicsObject::new(601., 0., 0., 0.),
+            PhysicsObject::new(-601., 10., 0., 0.),
+            PhysicsObject::new(0., 451., 0., 0.),
+            PhysicsObject::new(0., -451., 0., 0.),
+        ];
+        for obj in objects.iter_mut() {
+            obj.stay_in_bounds();
+        }
+        let items: Vec<_> = objects.iter().map(|o| o.pos()).collect();
+        assert_eq!(items, vec![Vector2(-599., 0.), Vector2(599., 10.), Vector2(0., -449.), Vector2(0., 449.)]);
+        let grid = grid(&items);
+
+        for &centre in &[Vector2(-600., 0.), Vector2(600., 0.), Vector2(0., 450.), Vector2(0., -450.), Vector2(0., 0.)] {
+            for &radius in &[5., 50., 500.] {
+                assert_eq!(sorted(grid.within(centre, radius)), brute_force(&items, centre, radius));
+            }
+        }
+        assert_eq!(grid.within(Vector2(-600., 0.), 5.), vec![0]);
+        assert_eq!(grid.within(Vector2(600., 10.), 5.), vec![1]);
+    }
+
+    #[test]
+    fn cleared_grids_find_nothing() {
+        let mut grid = grid(&[Vector2(0., 0.), Vector2(50., 50.)]);
+        grid.clear();
+        assert!(grid.within(Vector2(0., 0.), 1000.).is_empty());
+        grid.insert(7, Vector2(20., 20.));
+        assert_eq!(grid.within(Vector2(0., 0.), 1000.), vec![7]);
+    }
+}
@@ -3,7 +3,26 @@ extern crate bincode;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate rand;
+#[cfg(feature = "wasm")]
+extern crate wasmi;
 
 pub mod obj;
+pub mod math;
 pub mod net;
+pub mod grid;
+pub mod collision;
+pub mod weapon;
+pub mod damage;
+pub mod score;
+pub mod world;
+pub mod replay;
+pub mod control;
+pub mod bots;
+pub mod arena;
+pub mod clock;
+pub mod training;
+pub mod process;
+#[cfg(feature = "wasm")]
+pub mod wasm;
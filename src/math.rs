@@ -0,0 +1,187 @@
+use std::f32::consts::PI;
+
+use obj::{Vect, PhysicsObject};
+
+/// Angle between -π and π pointing the same way as `a`
+pub fn normalize_angle(a: f32) -> f32 {
+    let a = a % (2. * PI);
+    if a > PI {
+        a - 2. * PI
+    } else if a <= -PI {
+        a + 2. * PI
+    } else {
+        a
+    }
+}
+
+/// Radians to turn from facing `from` to facing `to` the short way round, positive for clockwise
+pub fn shortest_turn(from: f32, to: f32) -> f32 {
+    normalize_angle(to - from)
+}
+
+/// Smallest positive root of `a t² + b t + c`
+fn first_root(a: f32, b: f32, c: f32) -> Option<f32> {
+    let t = if a.abs() < 1e-6 {
+        -c / b
+    } else {
+        let disc = b * b - 4. * a * c;
+        if disc < 0. {
+            return None
+        }
+        let sqrt = disc.sqrt();
+        let (t1, t2) = ((-b - sqrt) / (2. * a), (-b + sqrt) / (2. * a));
+        let (t1, t2) = (t1.min(t2), t1.max(t2));
+        if t1 > 0. { t1 } else { t2 }
+    };
+    if t > 0. && t.is_finite() {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Seconds before something fired at `speed` catches up with a target at `offset` moving at `vel`
+///
+/// Both are relative to the shooter. Projectiles keep the velocity of the ship firing them, so
+/// `vel` should be the target's velocity minus the shooter's. `None` if it can't catch up.
+pub fn intercept_time(offset: Vect, vel: Vect, speed: f32) -> Option<f32> {
+    // |offset + vel t| = speed t
+    first_root(vel.dot(vel) - speed * speed, 2. * offset.dot(vel), offset.dot(offset))
+}
+
+/// Direction to fire something at `speed` in to hit a target at `offset` moving at `vel`
+///
+/// Takes the same relative position and velocity as `intercept_time`.
+pub fn intercept_direction(offset: Vect, vel: Vect, speed: f32) -> Option<f32> {
+    intercept_time(offset, vel, speed).map(|t| (offset + vel * t).direction())
+}
+
+/// When and how close something at `offset` moving at `vel` gets, both relative to us
+///
+/// Never looks back in time, something already getting further away is closest now.
+pub fn closest_approach(offset: Vect, vel: Vect) -> (f32, f32) {
+    let speed2 = vel.dot(vel);
+    let t = if speed2 > 0. { (-offset.dot(vel) / speed2).max(0.) } else { 0. };
+    (t, (offset + vel * t).length())
+}
+
+/// Seconds before something at `offset` moving at `vel` comes within `radius`, both relative to us
+///
+/// Zero if it already is and `None` if it never will.
+pub fn time_to_collision(offset: Vect, vel: Vect, radius: f32) -> Option<f32> {
+    let c = offset.dot(offset) - radius * radius;
+    if c <= 0. {
+        return Some(0.)
+    }
+    // |offset + vel t| = radius
+    first_root(vel.dot(vel), 2. * offset.dot(vel), c)
+}
+
+/// Where `obj` will be after each of `steps` ticks of `dt` seconds
+///
+/// It's stepped the same way the world steps planets and ships, wrapping around the edges of
+/// the map, so as long as its acceleration doesn't change the prediction is exact.
+pub fn predict_path(obj: &PhysicsObject, dt: f32, steps: usize) -> Vec<Vect> {
+    let mut obj = *obj;
+    (0..steps).map(|_| {
+        obj.update(dt);
+        obj.stay_in_bounds();
+        obj.pos()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj::Vector2;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn angles_normalize() {
+        assert!(close(normalize_angle(3. * PI), PI));
+        assert!(close(normalize_angle(-PI), PI));
+        assert!(close(normalize_angle(2.5 * PI), 0.5 * PI));
+        assert!(close(normalize_angle(-2.5 * PI), -0.5 * PI));
+        assert!(close(normalize_angle(0.3), 0.3));
+    }
+
+    #[test]
+    fn turns_go_the_short_way() {
+        assert!(close(shortest_turn(0.1, -0.1), -0.2));
+        assert!(close(shortest_turn(PI - 0.1, -PI + 0.1), 0.2));
+        assert!(close(shortest_turn(-PI + 0.1, PI - 0.1), -0.2));
+        assert!(close(shortest_turn(1., 1. + 4. * PI), 0.));
+    }
+
+    #[test]
+    fn intercepts_still_target() {
+        let t = intercept_time(Vector2(400., 0.), Vector2(0., 0.), 400.).unwrap();
+        assert!(close(t, 1.));
+        assert!(close(intercept_direction(Vector2(0., -200.), Vector2(0., 0.), 400.).unwrap(), -PI / 2.));
+    }
+
+    #[test]
+    fn intercepts_crossing_target() {
+        let (offset, vel, speed) = (Vector2(300., 0.), Vector2(0., 100.), 400.);
+        let t = intercept_time(offset, vel, speed).unwrap();
+        let dir = intercept_direction(offset, vel, speed).unwrap();
+        let shot = speed * t * Vector2::unit_vector(dir);
+        let target = offset + vel * t;
+        assert!(shot.distance_to(target) < 1e-2);
+        assert!(dir > 0.);
+    }
+
+    #[test]
+    fn cant_catch_faster_target() {
+        assert_eq!(intercept_time(Vector2(100., 0.), Vector2(500., 0.), 400.), None);
+        // Just as fast and coming straight at us is fine
+        assert!(close(intercept_time(Vector2(800., 0.), Vector2(-400., 0.), 400.).unwrap(), 1.));
+    }
+
+    #[test]
+    fn closest_approach_of_passing_object() {
+        let (t, d) = closest_approach(Vector2(-100., 30.), Vector2(50., 0.));
+        assert!(close(t, 2.));
+        assert!(close(d, 30.));
+        // Moving away is closest now
+        let (t, d) = closest_approach(Vector2(100., 0.), Vector2(10., 0.));
+        assert!(close(t, 0.));
+        assert!(close(d, 100.));
+        let (t, d) = closest_approach(Vector2(3., 4.), Vector2(0., 0.));
+        assert_eq!((t, d), (0., 5.));
+    }
+
+    #[test]
+    fn collisions() {
+        assert!(close(time_to_collision(Vector2(100., 0.), Vector2(-10., 0.), 20.).unwrap(), 8.));
+        assert_eq!(time_to_collision(Vector2(10., 0.), Vector2(5., 0.), 20.), Some(0.));
+        assert_eq!(time_to_collision(Vector2(100., 50.), Vector2(-10., 0.), 20.), None);
+        assert_eq!(time_to_collision(Vector2(100., 0.), Vector2(10., 0.), 20.), None);
+    }
+
+    #[test]
+    fn paths_match_the_integrator() {
+        let mut obj = PhysicsObject::new(0., 0., 30., -20.);
+        obj.acceleration = Vector2(5., 10.);
+        let path = predict_path(&obj, 1. / 60., 120);
+        assert_eq!(path.len(), 120);
+        // Constant acceleration is integrated exactly
+        assert!(path[119].distance_to(Vector2(70., -20.)) < 1e-2);
+        for &pos in &path {
+            obj.update(1. / 60.);
+            obj.stay_in_bounds();
+            assert_eq!(pos, obj.pos());
+        }
+    }
+
+    #[test]
+    fn paths_wrap_around() {
+        let obj = PhysicsObject::new(590., 0., 600., 0.);
+        let path = predict_path(&obj, 0.1, 2);
+        assert!(path[0].0 < 0.);
+        assert!(path[1].0 > path[0].0);
+    }
+}
@@ -1,22 +1,99 @@
-use super::obj::{PhysicsObject, RotatableObject};
+use super::obj::{Vect, PhysicsObject, RotatableObject};
+use super::weapon::WeaponId;
+use super::damage::Status;
+use super::score::Stats;
+use super::control::{DebugOutput, DebugPiece};
 
 use std::net::{UdpSocket, ToSocketAddrs, SocketAddr};
 use std::collections::BTreeMap;
 use std::io::Error;
+use std::time::Duration;
 
 use bincode::{serialize, deserialize, Bounded};
+use serde::Serialize;
 pub use bincode::serialized_size;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ClientPacket {
+    /// Joins whichever room has space
     Connect,
+    /// Asks for the list of rooms without joining any
+    ListRooms,
+    JoinRoom(RoomId),
+    /// Watches a room without getting a ship
+    Spectate(RoomId),
     PlayerImpulse(f32),
     PlayerRotate(f32),
-    Shoot,
+    Shoot {
+        weapon: WeaponId,
+    },
+    /// Hands the ship over to the room's fill-in bot, or takes it back
+    Autopilot(bool),
     Disconnect,
 }
 
 pub type Idx = u16;
+pub type TeamId = u8;
+pub type RoomId = u16;
+
+/// Something that can be damaged
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    Planet(Idx),
+    Player(Idx),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModeKind {
+    Deathmatch,
+    TeamDeathmatch,
+    LastShipStanding,
+    PlanetDefence,
+}
+
+impl ModeKind {
+    /// Points `stats` are worth in this mode
+    pub fn score(self, stats: &Stats) -> i32 {
+        match self {
+            ModeKind::PlanetDefence => stats.kills as i32 + 5 * stats.planets_destroyed as i32,
+            _ => stats.score(),
+        }
+    }
+}
+
+/// How a match ended
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Player(Idx),
+    Team(TeamId),
+    Draw,
+}
+
+/// Where the server is in the life of a match
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchPhase {
+    /// Not enough players to start a match, everyone can fly around freely
+    Waiting,
+    /// Practice before the match, nothing counts
+    Warmup,
+    /// The world has been reset and ships are frozen until the match starts
+    Countdown,
+    Running,
+    /// The match is over and ships are frozen while the results are shown
+    Results(Outcome),
+}
+
+/// What a room on the server is up to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub name: String,
+    pub mode: ModeKind,
+    pub players: u16,
+    pub max_players: u16,
+    pub spectators: u16,
+    pub phase: MatchPhase,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ServerPacket {
@@ -31,7 +108,53 @@ pub enum ServerPacket {
     DeletePlayer(Idx),
     DeleteLasers(Vec<Idx>),
     DeletePlanets(Vec<Idx>),
-    UpdateHealth(u8),
+    /// Health, shields and systems of the receiving player's own ship
+    UpdateStatus(Status),
+    /// `target` was hit for `damage` by a projectile fired by player `by`
+    Damage {
+        target: Target,
+        by: Idx,
+        damage: u8,
+    },
+    /// Player `player` blew up at `pos`
+    Died {
+        player: Idx,
+        killer: Option<Idx>,
+        pos: Vect,
+        /// Seconds until the ship is back, `None` if it's out of lives
+        respawn_in: Option<f32>,
+    },
+    Respawned(Idx, RotatableObject),
+    /// Score and stats of some of the players, large scoreboards are split over several packets
+    Scoreboard(Vec<(Idx, i32, Stats)>),
+    /// Rules of the match being played
+    GameMode {
+        mode: ModeKind,
+        teams: u8,
+        time_limit: Option<f32>,
+    },
+    /// Sent to a player when they join, telling them which ship is theirs
+    Joined {
+        id: Idx,
+        team: Option<TeamId>,
+    },
+    /// Team player `Idx` is on
+    Team(Idx, Option<TeamId>),
+    MatchOver(Outcome),
+    /// The match entered a new phase, which ends in `time_left` seconds if it's timed
+    Phase {
+        phase: MatchPhase,
+        time_left: Option<f32>,
+    },
+    /// Some of the rooms on the server, sent when asked for or when a room couldn't be joined
+    Rooms(Vec<RoomInfo>),
+    /// Part of what the bot flying the receiving player's ship showed about its last tick
+    ///
+    /// The `first` part replaces whatever it showed before.
+    Debug {
+        first: bool,
+        pieces: Vec<DebugPiece>,
+    },
     DisconnectAck
 }
 
@@ -45,19 +168,72 @@ const BUFFER_SIZE64: u64 = BUFFER_SIZE as u64;
 const BUFFER_SIZE_SRV: usize = 20;
 const BUFFER_SIZE_SRV64: u64 = BUFFER_SIZE_SRV as u64;
 
+/// Splits `items` into as few lists as fit in packets that are `empty` bytes without any, at least one
+///
+/// Items too big for a packet of their own are left out.
+fn split_to_fit<T: Serialize, I: IntoIterator<Item=T>>(items: I, empty: u64) -> Vec<Vec<T>> {
+    let mut lists = Vec::new();
+    let (mut list, mut size) = (Vec::new(), empty);
+    for item in items {
+        let item_size = serialized_size(&item);
+        if empty + item_size > BUFFER_SIZE64 {
+            continue
+        }
+        if size + item_size > BUFFER_SIZE64 {
+            lists.push(::std::mem::take(&mut list));
+            size = empty;
+        }
+        list.push(item);
+        size += item_size;
+    }
+    lists.push(list);
+    lists
+}
+
+/// Splits `output` into as few `Debug` packets as it fits in, at least one
+///
+/// Pieces too big for a packet of their own are left out.
+pub fn debug_packets(output: DebugOutput) -> Vec<ServerPacket> {
+    let empty = serialized_size(&ServerPacket::Debug{first: true, pieces: Vec::new()});
+    split_to_fit(output.into_pieces(), empty).into_iter().enumerate()
+        .map(|(n, pieces)| ServerPacket::Debug{first: n == 0, pieces})
+        .collect()
+}
+
+/// Splits `rooms` into as few `Rooms` packets as they fit in, at least one
+pub fn rooms_packets(rooms: Vec<RoomInfo>) -> Vec<ServerPacket> {
+    let empty = serialized_size(&ServerPacket::Rooms(Vec::new()));
+    split_to_fit(rooms, empty).into_iter().map(ServerPacket::Rooms).collect()
+}
+
 pub struct ClientSocket(UdpSocket);
 
 impl ClientSocket {
     pub fn new<S: ToSocketAddrs>(server: S) -> Self {
+        let s = Self::bind(server);
+        s.send(ClientPacket::Connect).unwrap();
+        s
+    }
+    /// Connects to a specific room on the server
+    pub fn join<S: ToSocketAddrs>(server: S, room: RoomId) -> Self {
+        let s = Self::bind(server);
+        s.send(ClientPacket::JoinRoom(room)).unwrap();
+        s
+    }
+    /// Sets up a socket talking to `server` without joining any room
+    pub fn bind<S: ToSocketAddrs>(server: S) -> Self {
         let s = UdpSocket::bind("0.0.0.0:0").unwrap();
         s.connect(server).unwrap();
         let s = ClientSocket(s);
-        s.send(ClientPacket::Connect).unwrap();
         if let Ok(addr) = s.0.local_addr() {
             println!("Bound to {}", addr);
         }
         s
     }
+    /// Makes `recv` give up after `timeout`, or never if it's `None`
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.0.set_read_timeout(timeout)
+    }
     pub fn recv(&self) -> Result<ServerPacket, Error> {
         let mut buf = [0u8; BUFFER_SIZE];
         self.0.recv(&mut buf)
@@ -97,3 +273,68 @@ impl ServerSocket {
         self.0.send_to(&data, addr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use control::{Shape, MAX_SHAPES};
+    use obj::Vector2;
+
+    #[test]
+    fn debug_output_is_split_over_packets_that_fit() {
+        let mut output = DebugOutput::default();
+        output.log("x".repeat(2 * BUFFER_SIZE));
+        output.log("chasing 3".to_owned());
+        output.value("distance".to_owned(), 420.);
+        for n in 0..MAX_SHAPES {
+            output.shape(Shape::Text{pos: Vector2(n as f32, 0.), text: "target".to_owned()});
+        }
+
+        let packets = debug_packets(output.clone());
+        assert!(packets.len() > 1);
+        let mut received = DebugOutput::default();
+        for (n, packet) in packets.into_iter().enumerate() {
+            assert!(serialize(&packet, Bounded(BUFFER_SIZE64)).is_ok());
+            match packet {
+                ServerPacket::Debug{first, pieces} => {
+                    assert_eq!(first, n == 0);
+                    for piece in pieces {
+                        received.add(piece);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        // All but the line too long to send
+        output.log.remove(0);
+        assert_eq!(received, output);
+
+        assert_eq!(debug_packets(DebugOutput::default()).len(), 1);
+    }
+
+    #[test]
+    fn rooms_are_split_over_packets_that_fit() {
+        let rooms: Vec<_> = (0..40).map(|id| RoomInfo {
+            id,
+            name: format!("room with a long name {}", "x".repeat(id as usize * 2)),
+            mode: ModeKind::Deathmatch,
+            players: 0,
+            max_players: 8,
+            spectators: 0,
+            phase: MatchPhase::Waiting,
+        }).collect();
+
+        let packets = rooms_packets(rooms.clone());
+        assert!(packets.len() > 1);
+        let mut received = Vec::new();
+        for packet in packets {
+            assert!(serialize(&packet, Bounded(BUFFER_SIZE64)).is_ok());
+            match packet {
+                ServerPacket::Rooms(rooms) => received.extend(rooms),
+                _ => unreachable!(),
+            }
+        }
+        let names = |rooms: &[RoomInfo]| rooms.iter().map(|r| (r.id, r.name.clone())).collect::<Vec<_>>();
+        assert_eq!(names(&received), names(&rooms));
+    }
+}
@@ -1,9 +1,17 @@
 use std::ops::{Deref, DerefMut};
 
+use std::f32::consts::PI;
+
+use net::{Idx, TeamId};
+use weapon::{weapon, Weapon, WeaponId, Behaviour, WEAPONS, WEAPON_COUNT};
+use damage::{Status, System};
+use score::Stats;
+use math::normalize_angle;
+
 pub type Vect = Vector2<f32>;
 pub use simple_vector2d::Vector2;
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct PhysicsObject {
     position: Vect,
@@ -11,7 +19,7 @@ pub struct PhysicsObject {
     pub acceleration: Vect,
 }
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct RotatableObject {
     physics_obj: PhysicsObject,
@@ -27,9 +35,14 @@ impl PhysicsObject {
         }
     }
     pub fn update(&mut self, dt: f32) {
-        self.position += 0.5 * self.acceleration * dt * dt + self.velocity * dt;
+        self.position += self.displacement(dt);
         self.velocity += self.acceleration * dt;
     }
+    /// How far `update` would move this object in `dt` seconds
+    #[inline]
+    pub fn displacement(&self, dt: f32) -> Vect {
+        0.5 * self.acceleration * dt * dt + self.velocity * dt
+    }
     #[inline]
     pub fn stay_in_bounds(&mut self) -> bool {
         stay_in_bounds(&mut self.position)
@@ -40,6 +53,9 @@ impl PhysicsObject {
     pub fn vel(&self) -> Vect {
         self.velocity
     }
+    pub fn set_vel(&mut self, v: Vect) {
+        self.velocity = v;
+    }
 }
 
 impl RotatableObject {
@@ -68,34 +84,192 @@ impl DerefMut for RotatableObject {
     }
 }
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct Planet {
     pub obj: PhysicsObject,
-    pub health: u8
+    pub status: Status,
+    /// Team this is the home planet of
+    pub team: Option<TeamId>,
 }
 
-#[derive(Debug, Copy, Clone)]
+impl Default for Planet {
+    fn default() -> Self {
+        Planet {
+            obj: Default::default(),
+            status: Status::planet(),
+            team: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct Player{
     pub obj: RotatableObject,
-    pub health: u8
+    pub status: Status,
+    /// Forward acceleration the engines are asked for
+    pub thrust: f32,
+    /// Seconds left before each weapon can fire again
+    pub reload: [f32; WEAPON_COUNT],
+    /// Seconds until a dead ship respawns, infinite if it won't
+    pub respawn: Option<f32>,
+    /// Lives left, if limited
+    pub lives: Option<u8>,
+    pub team: Option<TeamId>,
+    pub stats: Stats,
 }
 
 impl Default for Player {
     fn default() -> Self {
         Player {
             obj: Default::default(),
-            health: 5
+            status: Status::ship(),
+            thrust: 0.,
+            reload: [0.; WEAPON_COUNT],
+            respawn: None,
+            lives: None,
+            team: None,
+            stats: Stats::default(),
         }
     }
 }
 
+impl Player {
+    #[inline]
+    pub fn is_alive(&self) -> bool {
+        self.respawn.is_none()
+    }
+    /// Whether the ship is dead for good
+    #[inline]
+    pub fn is_out(&self) -> bool {
+        self.respawn.map(f32::is_infinite).unwrap_or(false)
+    }
+    /// Marks the ship as dead, using up a life
+    ///
+    /// Returns the seconds until it respawns, or `None` if it's out of lives.
+    pub fn kill(&mut self, respawn_time: f32) -> Option<f32> {
+        self.thrust = 0.;
+        self.lives = self.lives.map(|l| l.saturating_sub(1));
+        let respawn = if self.lives == Some(0) {
+            None
+        } else {
+            Some(respawn_time)
+        };
+        self.respawn = Some(respawn.unwrap_or(f32::INFINITY));
+        respawn
+    }
+    /// Brings the ship back fully repaired at `pos`
+    pub fn spawn(&mut self, pos: Vect, rotation: f32) {
+        self.obj = RotatableObject::new(pos, Vector2(0., 0.), rotation);
+        self.status = Status::ship();
+        self.thrust = 0.;
+        self.reload = [0.; WEAPON_COUNT];
+        self.respawn = None;
+    }
+    /// Fires `weapon` if it has reloaded
+    pub fn try_fire(&mut self, owner: Idx, weapon: WeaponId) -> Option<Laser> {
+        if !self.is_alive() || !self.status.works(System::Weapons) {
+            return None
+        }
+        let reload = self.reload.get_mut(weapon as usize)?;
+        if *reload > 0. {
+            return None
+        }
+        let laser = Laser {
+            team: self.team,
+            .. Laser::fire(owner, &self.obj, weapon)?
+        };
+        *reload = laser.weapon().cooldown;
+        Some(laser)
+    }
+    /// Moves the ship, or counts down to its respawn if it's dead
+    pub fn update(&mut self, dt: f32) {
+        if let Some(ref mut respawn) = self.respawn {
+            *respawn -= dt;
+            return
+        }
+        for reload in self.reload.iter_mut() {
+            *reload = (*reload - dt).max(0.);
+        }
+        self.status.update(dt);
+
+        self.obj.acceleration = if self.status.works(System::Engines) {
+            self.thrust * Vector2::unit_vector(self.obj.rotation)
+        } else {
+            Vector2(0., 0.)
+        };
+        self.obj.update(dt);
+    }
+}
+
+/// A projectile fired by any weapon
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct Laser {
+    pub obj: RotatableObject,
+    pub owner: Idx,
+    pub weapon: WeaponId,
+    /// Team of the ship that fired it
+    pub team: Option<TeamId>,
+    pub damage: u8,
+    pub ttl: f32,
+}
+
+impl Laser {
+    /// Shoots a projectile from `ship`
+    ///
+    /// Returns `None` if there is no such weapon.
+    pub fn fire(owner: Idx, ship: &RotatableObject, weapon_id: WeaponId) -> Option<Self> {
+        let weapon = weapon(weapon_id)?;
+        let dir = Vector2::unit_vector(ship.rotation);
+
+        let obj = match weapon.behaviour {
+            Behaviour::Mine => RotatableObject::new(ship.pos() - 42. * dir, Vector2(0., 0.), ship.rotation),
+            _ => RotatableObject::new(ship.pos() + 42. * dir,
+                ship.vel() + weapon.speed * dir, ship.rotation),
+        };
+
+        Some(Laser {
+            obj,
+            owner,
+            weapon: weapon_id,
+            team: None,
+            damage: weapon.damage,
+            ttl: weapon.ttl,
+        })
+    }
+    #[inline]
+    pub fn weapon(&self) -> &'static Weapon {
+        &WEAPONS[self.weapon as usize]
+    }
+    /// Turns the projectile towards `target` as fast as its weapon allows
+    ///
+    /// Does nothing for anything but homing weapons.
+    pub fn steer_towards(&mut self, target: Vect, dt: f32) {
+        if let Behaviour::Homing{turn_rate, ..} = self.weapon().behaviour {
+            let diff = normalize_angle(self.obj.pos().direction_to(target) - self.obj.rotation);
+            let max_turn = turn_rate * dt;
+            self.obj.rotation += diff.max(-max_turn).min(max_turn);
+
+            let vel = self.obj.vel().length() * Vector2::unit_vector(self.obj.rotation);
+            self.obj.set_vel(vel);
+        }
+    }
+    /// Moves the projectile and returns whether it has expired
+    pub fn update(&mut self, dt: f32) -> bool {
+        self.obj.update(dt);
+        self.ttl -= dt;
+        self.ttl <= 0.
+    }
+}
+
 impl Planet {
     pub fn new(x: f32, y: f32, vx: f32, vy: f32) -> Self {
         Planet {
             obj: PhysicsObject::new(x, y, vx, vy),
-            health: 5
+            status: Status::planet(),
+            team: None,
         }
     }
 }
@@ -112,6 +286,28 @@ impl Rand for Planet {
 const W: f32 = 1200./2.;
 const H: f32 =  900./2.;
 
+/// Random point within the bounds of the map
+pub fn random_position<R: Rng>(rng: &mut R) -> Vect {
+    Vector2(rng.gen_range(-W, W), rng.gen_range(-H, H))
+}
+
+/// Random point within the part of the map belonging to `team` out of `teams`
+///
+/// The map is split into slices around the centre, team 0's facing right.
+pub fn random_team_position<R: Rng>(rng: &mut R, team: TeamId, teams: u8) -> Vect {
+    let slice = 2. * PI / teams as f32;
+    let angle = slice * (team as f32 + rng.gen_range(-0.5, 0.5));
+    let dist = rng.gen_range(0.3, 0.9);
+    let (y, x) = angle.sin_cos();
+    Vector2(dist * W * x, dist * H * y)
+}
+
+/// Whether things belonging to these teams are on the same side
+#[inline]
+pub fn allies(a: Option<TeamId>, b: Option<TeamId>) -> bool {
+    a.is_some() && a == b
+}
+
 /// Wraps `p` if out of bounds
 fn stay_in_bounds(p: &mut Vect) -> bool {
     let mut out_of_bounds;
@@ -0,0 +1,274 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Child, Stdio};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+use control::{ShipController, Sensors, Controls, DebugOutput};
+
+/// A line a program answers with, its `Controls` and optionally a `debug` field
+#[derive(Deserialize)]
+struct Answer {
+    #[serde(flatten)]
+    controls: Controls,
+    #[serde(default)]
+    debug: DebugOutput,
+}
+
+/// A ship program running as a child process, speaking JSON one line at a time
+///
+/// Every tick it gets its `Sensors` as a line on stdin and has to answer with its `Controls`
+/// on a line of stdout within the time limit, optionally with its `DebugOutput` in a `debug`
+/// field. Late answers are thrown away and the ship does nothing for that tick. A program that
+/// stops reading its stdin, so its readings can't even be sent within the time limit, is killed.
+/// Anything it writes to stderr is passed through.
+pub struct Program {
+    command: String,
+    child: Child,
+    /// Lines for the writer thread to send to the program's stdin
+    input: Sender<String>,
+    /// How each line sent to `input` went, once it has been written
+    written: Receiver<io::Result<()>>,
+    lines: Receiver<String>,
+    time_limit: Duration,
+    /// Readings sent that haven't been answered yet
+    pending: usize,
+    crashed: bool,
+    error: Option<String>,
+    debug: DebugOutput,
+}
+
+impl Program {
+    /// Starts `command`, which is split on whitespace into the program and its arguments
+    pub fn spawn(command: &str, time_limit: Duration) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let (input, to_write) = channel::<String>();
+        let (done, written) = channel();
+        thread::spawn(move || {
+            for line in to_write {
+                let result = stdin.write_all(line.as_bytes()).and_then(|()| stdin.flush());
+                let failed = result.is_err();
+                if done.send(result).is_err() || failed {
+                    break
+                }
+            }
+        });
+
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (tx, lines) = channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                match line {
+                    Ok(line) => if tx.send(line).is_err() {
+                        break
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Program {
+            command: command.to_owned(),
+            child,
+            input,
+            written,
+            lines,
+            time_limit,
+            pending: 0,
+            crashed: false,
+            error: None,
+            debug: DebugOutput::default(),
+        })
+    }
+    fn crash(&mut self, reason: &str) -> Controls {
+        if !self.crashed {
+            eprintln!("Ship program {:?} crashed: {}", self.command, reason);
+            self.crashed = true;
+            self.error = Some(format!("crashed: {}", reason));
+        }
+        Controls::default()
+    }
+}
+
+impl ShipController for Program {
+    fn control(&mut self, sensors: &Sensors) -> Controls {
+        if self.crashed {
+            return Controls::default()
+        }
+        let deadline = Instant::now() + self.time_limit;
+        let mut line = match serde_json::to_string(sensors) {
+            Ok(line) => line,
+            Err(e) => return self.crash(&e.to_string()),
+        };
+        line.push('\n');
+        if self.input.send(line).is_err() {
+            return self.crash("closed its input")
+        }
+        match self.written.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return self.crash(&e.to_string()),
+            Err(RecvTimeoutError::Timeout) => {
+                // Killing it closes the pipe the writer thread is stuck on
+                let _ = self.child.kill();
+                return self.crash("stopped reading its input")
+            }
+            Err(RecvTimeoutError::Disconnected) => return self.crash("closed its input"),
+        }
+        self.pending += 1;
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(timeout) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.error = Some("took too long to answer".to_owned());
+                    return Controls::default()
+                }
+                Err(RecvTimeoutError::Disconnected) => return self.crash("closed its output"),
+            };
+            self.pending -= 1;
+            if self.pending > 0 {
+                continue
+            }
+            return match serde_json::from_str::<Answer>(&line) {
+                Ok(mut answer) => {
+                    self.error = None;
+                    answer.debug.truncate();
+                    self.debug = answer.debug;
+                    answer.controls
+                }
+                Err(e) => {
+                    eprintln!("Ship program {:?} sent {:?}: {}", self.command, line, e);
+                    self.error = Some(format!("sent {:?}: {}", line, e));
+                    Controls::default()
+                }
+            }
+        }
+    }
+    fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+    fn debug(&mut self) -> DebugOutput {
+        ::std::mem::take(&mut self.debug)
+    }
+    fn has_crashed(&self) -> bool {
+        self.crashed
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::env::temp_dir;
+    use std::fs::{write, remove_file};
+    use std::path::{Path, PathBuf};
+    use obj::Player;
+    use control::sense;
+
+    /// Writes a shell script for a test to run as its program
+    fn script(name: &str, source: &str) -> PathBuf {
+        let path = temp_dir().join(format!("velox-program-test-{}-{}.sh", ::std::process::id(), name));
+        write(&path, source).unwrap();
+        path
+    }
+
+    fn spawn(script: &Path, time_limit: u64) -> Program {
+        Program::spawn(&format!("sh {}", script.display()), Duration::from_millis(time_limit)).unwrap()
+    }
+
+    fn sensors() -> Sensors {
+        crowded_sensors(1)
+    }
+
+    /// Readings of a ship with `ships - 1` others right next to it
+    fn crowded_sensors(ships: u16) -> Sensors {
+        let players: BTreeMap<_, _> = (0..ships).map(|i| (i, Player::default())).collect();
+        sense(&players, &BTreeMap::new(), &BTreeMap::new(), 0, 0.)
+    }
+
+    #[test]
+    fn late_answers_are_thrown_away() {
+        let path = script("late", "read line; sleep 0.5; echo '{\"thrust\": -1}'\nwhile read line; do echo '{\"thrust\": 1}'; done\n");
+        let mut program = spawn(&path, 100);
+
+        assert_eq!(program.control(&sensors()), Controls::default());
+        assert_eq!(program.error(), Some("took too long to answer".to_owned()));
+        assert_eq!(program.pending, 1);
+        let _ = remove_file(&path);
+
+        // The late answer comes in before this tick's, and is skipped
+        thread::sleep(Duration::from_millis(700));
+        assert_eq!(program.control(&sensors()).thrust, 1.);
+        assert_eq!(program.error(), None);
+        assert_eq!(program.pending, 0);
+        assert_eq!(program.control(&sensors()).thrust, 1.);
+        assert!(!program.has_crashed());
+    }
+
+    #[test]
+    fn nonsense_is_ignored() {
+        let path = script("nonsense", "read line; echo nonsense\nwhile read line; do echo '{\"turn\": 1}'; done\n");
+        let mut program = spawn(&path, 2000);
+
+        assert_eq!(program.control(&sensors()), Controls::default());
+        assert!(program.error().unwrap().starts_with("sent \"nonsense\""));
+        let _ = remove_file(&path);
+
+        assert_eq!(program.control(&sensors()).turn, 1.);
+        assert_eq!(program.error(), None);
+        assert!(!program.has_crashed());
+    }
+
+    #[test]
+    fn exiting_crashes_the_program() {
+        let path = script("exit", "read line; exit 0\n");
+        let mut program = spawn(&path, 2000);
+
+        assert_eq!(program.control(&sensors()), Controls::default());
+        assert!(program.has_crashed());
+        assert_eq!(program.error(), Some("crashed: closed its output".to_owned()));
+        let _ = remove_file(&path);
+
+        assert_eq!(program.control(&sensors()), Controls::default());
+        assert!(program.has_crashed());
+    }
+
+    #[test]
+    fn programs_that_stop_reading_are_killed() {
+        let path = script("deaf", "exec sleep 1000\n");
+        let mut program = spawn(&path, 50);
+        // Big enough readings that the pipe fills up within a few ticks
+        let sensors = crowded_sensors(500);
+
+        let start = Instant::now();
+        while !program.has_crashed() {
+            assert_eq!(program.control(&sensors), Controls::default());
+            assert!(start.elapsed() < Duration::from_secs(10));
+        }
+        assert_eq!(program.error(), Some("crashed: stopped reading its input".to_owned()));
+        let killed = Instant::now();
+        while program.child.try_wait().unwrap().is_none() {
+            assert!(killed.elapsed() < Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = remove_file(&path);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use bincode::{self, serialize_into, deserialize_from, Infinite};
+
+use net::ModeKind;
+use world::{Rules, Snapshot, Input, World, Event};
+
+/// Bumped whenever the replay format changes
+pub const REPLAY_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"VXRP";
+
+/// How a recorded match started out
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Header {
+    pub version: u32,
+    pub room: String,
+    pub mode: ModeKind,
+    pub rules: Rules,
+    /// Seed of the simulation's random number generator
+    pub seed: u32,
+    pub initial: Snapshot,
+}
+
+/// One step of the simulation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Frame {
+    /// Inputs applied before stepping
+    pub inputs: Vec<Input>,
+    pub delta: f32,
+    /// The world after the step, only recorded every so often to check replays against
+    pub keyframe: Option<Snapshot>,
+}
+
+fn into_io(e: bincode::ErrorKind) -> io::Error {
+    match e {
+        bincode::ErrorKind::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Writes a replay as a match goes on
+pub struct Recorder {
+    out: BufWriter<File>,
+    inputs: Vec<Input>,
+    frames: u32,
+    keyframe_interval: u32,
+}
+
+impl Recorder {
+    /// Starts a replay at `path`, putting a keyframe in every `keyframe_interval` frames
+    pub fn create<P: AsRef<Path>>(path: P, header: &Header, keyframe_interval: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        serialize_into(&mut out, header, Infinite).map_err(|e| into_io(*e))?;
+        Ok(Recorder {
+            out,
+            inputs: Vec::new(),
+            frames: 0,
+            keyframe_interval,
+        })
+    }
+    /// Queues an input for the next frame
+    pub fn input(&mut self, input: Input) {
+        self.inputs.push(input);
+    }
+    /// Records a step of `delta` seconds, `snapshot` is only called when a keyframe is due
+    pub fn frame<F: FnOnce() -> Snapshot>(&mut self, delta: f32, snapshot: F) -> io::Result<()> {
+        self.frames += 1;
+        let keyframe = if self.keyframe_interval > 0 && self.frames.is_multiple_of(self.keyframe_interval) {
+            Some(snapshot())
+        } else {
+            None
+        };
+        let frame = Frame {
+            inputs: ::std::mem::take(&mut self.inputs),
+            delta,
+            keyframe,
+        };
+        serialize_into(&mut self.out, &frame, Infinite).map_err(|e| into_io(*e))
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A whole recorded match
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: Header,
+    pub frames: Vec<Frame>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a replay file"))
+        }
+        let header: Header = deserialize_from(&mut input, Infinite).map_err(|e| into_io(*e))?;
+        if header.version != REPLAY_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("replay is version {}, only version {} is supported", header.version, REPLAY_VERSION)))
+        }
+
+        let mut frames = Vec::new();
+        loop {
+            match deserialize_from(&mut input, Infinite).map_err(|e| into_io(*e)) {
+                Ok(frame) => frames.push(frame),
+                // A match cut short leaves a partly written frame behind
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Replay {
+            header,
+            frames,
+        })
+    }
+}
+
+impl Replay {
+    /// Simulates the whole match again and checks it against every keyframe
+    ///
+    /// Returns how many keyframes matched, or the first frame that didn't.
+    pub fn verify(&self) -> Result<usize, usize> {
+        let mut playback = Playback::new(self.clone());
+        let mut checked = 0;
+        while playback.step().is_some() {
+            if let Some(ref keyframe) = self.frames[playback.frame() - 1].keyframe {
+                if *keyframe != playback.world().snapshot() {
+                    return Err(playback.frame() - 1)
+                }
+                checked += 1;
+            }
+        }
+        Ok(checked)
+    }
+}
+
+/// Plays a replay back by simulating it again
+pub struct Playback {
+    replay: Replay,
+    world: World,
+    /// Frames played so far
+    frame: usize,
+    /// Seconds into the match each frame starts at
+    starts: Vec<f32>,
+    /// Time passed that wasn't enough for the next frame yet
+    carry: f32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        let mut time = 0.;
+        let starts = replay.frames.iter().map(|f| {
+            let start = time;
+            time += f.delta;
+            start
+        }).collect();
+        Playback {
+            world: Self::start(&replay),
+            replay,
+            frame: 0,
+            starts,
+            carry: 0.,
+        }
+    }
+    fn start(replay: &Replay) -> World {
+        let header = &replay.header;
+        World::new(header.rules, header.seed, header.initial.clone())
+    }
+    #[inline]
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    #[inline]
+    pub fn header(&self) -> &Header {
+        &self.replay.header
+    }
+    #[inline]
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+    /// Seconds into the match
+    pub fn time(&self) -> f32 {
+        self.starts.get(self.frame).cloned().unwrap_or_else(|| self.duration())
+    }
+    pub fn duration(&self) -> f32 {
+        self.replay.frames.last().map(|f| f.delta).unwrap_or(0.) + self.starts.last().cloned().unwrap_or(0.)
+    }
+    /// Plays a single frame, returning `None` at the end
+    pub fn step(&mut self) -> Option<Vec<Event>> {
+        let frame = self.replay.frames.get(self.frame)?;
+        for input in frame.inputs.iter() {
+            self.world.apply(input);
+        }
+        self.frame += 1;
+        Some(self.world.step(frame.delta))
+    }
+    /// Plays every frame that fits in the next `dt` seconds
+    pub fn advance(&mut self, dt: f32) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.carry += dt;
+        while let Some(delta) = self.replay.frames.get(self.frame).map(|f| f.delta) {
+            if delta > self.carry {
+                break
+            }
+            self.carry -= delta;
+            events.extend(self.step().unwrap());
+        }
+        if self.is_finished() {
+            self.carry = 0.;
+        }
+        events
+    }
+    /// Jumps to `time` seconds into the match, going back means simulating from the start again
+    ///
+    /// Stays put if `time` isn't a number.
+    pub fn seek(&mut self, time: f32) {
+        if time.is_nan() {
+            return
+        }
+        let target = match self.starts.binary_search_by(|s| s.total_cmp(&time)) {
+            Ok(i) | Err(i) => i,
+        };
+        if target < self.frame {
+            self.world = Self::start(&self.replay);
+            self.frame = 0;
+        }
+        while self.frame < target {
+            self.step();
+        }
+        self.carry = 0.;
+    }
+}
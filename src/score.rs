@@ -0,0 +1,24 @@
+/// What a player has done so far in a match
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage_dealt: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+    pub planets_destroyed: u32,
+}
+
+impl Stats {
+    pub fn score(&self) -> i32 {
+        2 * self.kills as i32 + self.planets_destroyed as i32 - self.deaths as i32
+    }
+    /// Fraction of shots that hit anything
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.
+        } else {
+            self.shots_hit as f32 / self.shots_fired as f32
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use net::{Idx, TeamId};
+use world::Rules;
+use arena::Arena;
+use control::{ShipController, Sensors, Controls};
+
+/// How a training environment is set up
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Ships the caller flies, they get the lowest indices
+    pub agents: usize,
+    pub rules: Rules,
+    /// Simulated seconds before an episode is done
+    pub duration: f32,
+    /// Simulated seconds per step
+    pub tick: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            agents: 1,
+            rules: Rules::default(),
+            duration: 120.,
+            tick: 1. / 60.,
+        }
+    }
+}
+
+/// What came of one step of an episode
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Step {
+    pub observations: BTreeMap<Idx, Sensors>,
+    /// Change in score of every agent during the step
+    pub rewards: BTreeMap<Idx, f32>,
+    /// Whether the episode is over and needs a reset
+    pub done: bool,
+}
+
+/// Episodes of a match where some ships are flown step by step from outside, for training
+///
+/// Other ships are flown by the controllers `opponents` makes for every episode, taking turns
+/// between the teams the agents aren't on if there are any.
+pub struct Environment {
+    pub settings: Settings,
+    opponents: Box<dyn FnMut() -> Vec<Box<dyn ShipController>>>,
+    arena: Arena,
+    agents: Vec<Idx>,
+    scores: BTreeMap<Idx, i32>,
+}
+
+impl Environment {
+    pub fn new<F>(settings: Settings, opponents: F) -> Self
+    where F: FnMut() -> Vec<Box<dyn ShipController>> + 'static {
+        let mut env = Environment {
+            arena: Arena::new(settings.rules, 0),
+            settings,
+            opponents: Box::new(opponents),
+            agents: Vec::new(),
+            scores: BTreeMap::new(),
+        };
+        env.reset(0, None);
+        env
+    }
+    /// The arena of the current episode
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+    fn observations(&self) -> BTreeMap<Idx, Sensors> {
+        self.agents.iter().map(|&i| (i, self.arena.sense(i))).collect()
+    }
+    /// Starts a new episode with the agents on `team` and returns what they see at first
+    pub fn reset(&mut self, seed: u32, team: Option<TeamId>) -> BTreeMap<Idx, Sensors> {
+        self.arena = Arena::new(self.settings.rules, seed);
+        self.agents = (0..self.settings.agents).map(|_| self.arena.join(team)).collect();
+        let others: Vec<_> = (0..self.settings.rules.teams).filter(|&t| Some(t) != team).collect();
+        for (n, controller) in (self.opponents)().into_iter().enumerate() {
+            let team = if others.is_empty() { None } else { Some(others[n % others.len()]) };
+            self.arena.add(controller, team);
+        }
+        self.scores = self.agents.iter().map(|&i| (i, 0)).collect();
+        self.observations()
+    }
+    /// Has the agents follow `actions` for one step, agents without any do nothing
+    pub fn step(&mut self, actions: &BTreeMap<Idx, Controls>) -> Step {
+        let tick = self.settings.tick;
+        for &i in &self.agents {
+            let controls = actions.get(&i).cloned().unwrap_or_default();
+            self.arena.control(i, controls, tick);
+        }
+        self.arena.tick(tick);
+
+        let arena = &self.arena;
+        let rewards = self.scores.iter_mut()
+            .map(|(&i, score)| {
+                let new_score = arena.score(i);
+                let reward = (new_score - *score) as f32;
+                *score = new_score;
+                (i, reward)
+            })
+            .collect();
+
+        Step {
+            observations: self.observations(),
+            rewards,
+            done: self.arena.time >= self.settings.duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj::Vector2;
+    use control::bot;
+    use weapon::LASER;
+
+    fn environment(settings: Settings) -> Environment {
+        Environment::new(settings, || vec![bot("idle").unwrap(), bot("idle").unwrap()])
+    }
+
+    #[test]
+    fn agents_play_on_the_team_they_reset_with() {
+        let mut env = environment(Settings {
+            agents: 2,
+            rules: Rules{teams: 3, .. Rules::default()},
+            .. Settings::default()
+        });
+        let observations = env.reset(1, Some(1));
+        assert_eq!(observations.len(), 2);
+        assert!(observations.values().all(|s| s.team == Some(1)));
+
+        let players = &env.arena().world.players;
+        let opponents: Vec<_> = players.iter().filter(|&(i, _)| !observations.contains_key(i)).map(|(_, p)| p.team).collect();
+        assert_eq!(opponents, vec![Some(0), Some(2)]);
+
+        // Without teams there's no one to be on
+        let mut env = environment(Settings::default());
+        assert!(env.reset(1, None).values().all(|s| s.team.is_none()));
+        assert!(env.arena().world.players.values().all(|p| p.team.is_none()));
+    }
+
+    #[test]
+    fn episodes_reward_kills_and_end_after_their_duration() {
+        let mut env = environment(Settings {
+            duration: 1.,
+            tick: 0.1,
+            .. Settings::default()
+        });
+        let agent = *env.reset(2, None).keys().next().unwrap();
+        let target = agent + 1;
+        {
+            // A wreck waiting to happen right in front of the agent
+            let world = &mut env.arena.world;
+            world.planets.clear();
+            world.players.get_mut(&agent).unwrap().spawn(Vector2(0., 0.), 0.);
+            let target = world.players.get_mut(&target).unwrap();
+            target.spawn(Vector2(100., 0.), 0.);
+            target.status.shield = 0.;
+            target.status.health = 1;
+        }
+
+        let mut actions = BTreeMap::new();
+        actions.insert(agent, Controls{fire: Some(LASER), .. Controls::default()});
+        let step = env.step(&actions);
+        assert_eq!(step.rewards[&agent], 2.);
+        assert!(!step.done);
+        assert!(!env.arena().world.players[&target].is_alive());
+
+        let mut steps = 1;
+        while !env.step(&BTreeMap::new()).done {
+            steps += 1;
+            assert!(steps < 10, "still going after {} steps", steps);
+        }
+        assert!(env.arena().time >= 1.);
+
+        // Starting over brings everyone back with nothing to show for it
+        let observations = env.reset(2, None);
+        assert!(observations.values().all(|s| s.alive));
+        assert_eq!(env.step(&BTreeMap::new()).rewards[&agent], 0.);
+    }
+}
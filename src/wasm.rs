@@ -0,0 +1,283 @@
+use wasmi::{Engine, Config, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Caller, TypedFunc, Error};
+use wasmi::core::{TrapCode, F32};
+
+use control::{ShipController, Sensors, Controls, DebugOutput, Shape, READINGS, Reading};
+use obj::Vector2;
+use damage::Status;
+use weapon::WEAPON_COUNT;
+
+/// Module ship programs import the host API from
+const MODULE: &str = "velox";
+/// Fuel a program gets to spend every tick, roughly one per instruction
+pub const FUEL_PER_TICK: u64 = 100_000;
+/// Fuel a program gets to spend setting itself up
+pub const START_FUEL: u64 = 10 * FUEL_PER_TICK;
+/// Most bytes of memory a program can have
+pub const MEMORY_LIMIT: usize = 16 << 20;
+
+struct Host {
+    /// Readings for the tick being run, all zero while the program is starting up
+    sensors: Sensors,
+    controls: Controls,
+    debug: DebugOutput,
+    limits: StoreLimits,
+}
+
+impl Host {
+    fn reading(&self, name: &str, i: usize) -> f32 {
+        self.sensors.get(name, i).unwrap_or(0.)
+    }
+}
+
+/// Reads `len` bytes of text at `ptr` in the program's exported memory, if they're all there
+fn read_text(c: &Caller<'_, Host>, ptr: i32, len: i32) -> Option<String> {
+    let memory = c.get_export("memory")?.into_memory()?;
+    let bytes = memory.data(c).get(ptr as u32 as usize..)?.get(..len as u32 as usize)?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Defines the host API in `linker`
+///
+/// Readings are those of `Sensors::get`, the whole ones as `i32`. Everything reads as 0 while
+/// the program is starting up. Text is passed as a pointer into the exported `memory` and a
+/// length in bytes, text that isn't all there is ignored.
+fn host_api(linker: &mut Linker<Host>) -> Result<(), Error> {
+    for &(name, indexed, reading) in READINGS {
+        match (indexed, reading) {
+            (false, Reading::Number) => linker.func_wrap(MODULE, name, move |c: Caller<'_, Host>| {
+                F32::from_float(c.data().reading(name, 0))
+            })?,
+            (false, Reading::Whole) => linker.func_wrap(MODULE, name, move |c: Caller<'_, Host>| {
+                c.data().reading(name, 0) as i32
+            })?,
+            (true, Reading::Number) => linker.func_wrap(MODULE, name, move |c: Caller<'_, Host>, i: i32| {
+                F32::from_float(c.data().reading(name, i as usize))
+            })?,
+            (true, Reading::Whole) => linker.func_wrap(MODULE, name, move |c: Caller<'_, Host>, i: i32| {
+                c.data().reading(name, i as usize) as i32
+            })?,
+        };
+    }
+
+    linker.func_wrap(MODULE, "thrust", |mut c: Caller<'_, Host>, thrust: F32| c.data_mut().controls.thrust = thrust.to_float())?;
+    linker.func_wrap(MODULE, "turn", |mut c: Caller<'_, Host>, turn: F32| c.data_mut().controls.turn = turn.to_float())?;
+    linker.func_wrap(MODULE, "fire", |mut c: Caller<'_, Host>, weapon: i32| {
+        c.data_mut().controls.fire = if (0..=255).contains(&weapon) { Some(weapon as u8) } else { None };
+    })?;
+
+    linker.func_wrap(MODULE, "log", |mut c: Caller<'_, Host>, ptr: i32, len: i32| {
+        if let Some(line) = read_text(&c, ptr, len) {
+            c.data_mut().debug.log(line);
+        }
+    })?;
+    linker.func_wrap(MODULE, "value", |mut c: Caller<'_, Host>, ptr: i32, len: i32, value: F32| {
+        if let Some(name) = read_text(&c, ptr, len) {
+            c.data_mut().debug.value(name, value.to_float());
+        }
+    })?;
+    linker.func_wrap(MODULE, "line", |mut c: Caller<'_, Host>, x1: F32, y1: F32, x2: F32, y2: F32| {
+        c.data_mut().debug.shape(Shape::Line {
+            from: Vector2(x1.to_float(), y1.to_float()),
+            to: Vector2(x2.to_float(), y2.to_float()),
+        });
+    })?;
+    linker.func_wrap(MODULE, "circle", |mut c: Caller<'_, Host>, x: F32, y: F32, radius: F32| {
+        c.data_mut().debug.shape(Shape::Circle {
+            pos: Vector2(x.to_float(), y.to_float()),
+            radius: radius.to_float(),
+        });
+    })?;
+    linker.func_wrap(MODULE, "text", |mut c: Caller<'_, Host>, x: F32, y: F32, ptr: i32, len: i32| {
+        if let Some(text) = read_text(&c, ptr, len) {
+            c.data_mut().debug.shape(Shape::Text {
+                pos: Vector2(x.to_float(), y.to_float()),
+                text,
+            });
+        }
+    })?;
+    Ok(())
+}
+
+/// Readings a program sees while it's starting up
+fn starting_sensors() -> Sensors {
+    Sensors {
+        time: 0.,
+        id: 0,
+        team: None,
+        alive: false,
+        pos: Vector2::default(),
+        vel: Vector2::default(),
+        rotation: 0.,
+        status: Status::ship(),
+        reload: [0.; WEAPON_COUNT],
+        ships: Vec::new(),
+        planets: Vec::new(),
+        projectiles: Vec::new(),
+    }
+}
+
+/// A ship program compiled to WebAssembly
+///
+/// It imports what it needs from the `velox` module and exports a `tick` function that is
+/// called once every tick, with a limited amount of fuel to spend each time.
+/// Running out of fuel means doing nothing that tick, any other trap crashes the program.
+pub struct WasmProgram {
+    store: Store<Host>,
+    tick: TypedFunc<(), ()>,
+    crashed: bool,
+    error: Option<String>,
+}
+
+impl WasmProgram {
+    /// Compiles and starts the program in `wasm`, in the binary format
+    pub fn new(wasm: &[u8]) -> Result<Self, Error> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)?;
+
+        let mut store = Store::new(&engine, Host {
+            sensors: starting_sensors(),
+            controls: Controls::default(),
+            debug: DebugOutput::default(),
+            limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).instances(1).build(),
+        });
+        store.limiter(|host| &mut host.limits);
+        store.add_fuel(START_FUEL)?;
+
+        let mut linker = Linker::new(&engine);
+        host_api(&mut linker)?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        let tick = instance.get_typed_func(&store, "tick")?;
+
+        Ok(WasmProgram {
+            store,
+            tick,
+            crashed: false,
+            error: None,
+        })
+    }
+}
+
+impl ShipController for WasmProgram {
+    fn control(&mut self, sensors: &Sensors) -> Controls {
+        if self.crashed {
+            return Controls::default()
+        }
+        // Whatever is left, from starting up or the tick before, doesn't carry over
+        let remaining = self.store.consume_fuel(0).unwrap_or(0);
+        self.store.consume_fuel(remaining).unwrap();
+        self.store.add_fuel(FUEL_PER_TICK).unwrap();
+        {
+            let host = self.store.data_mut();
+            host.sensors = sensors.clone();
+            host.controls = Controls::default();
+            host.debug = DebugOutput::default();
+        }
+
+        match self.tick.call(&mut self.store, ()) {
+            Ok(()) => {
+                self.error = None;
+                self.store.data().controls
+            }
+            Err(ref trap) if matches!(trap.trap_code(), Some(TrapCode::OutOfFuel)) => {
+                self.error = Some("ran out of fuel".to_owned());
+                Controls::default()
+            }
+            Err(trap) => {
+                eprintln!("Ship program crashed: {}", trap);
+                self.crashed = true;
+                self.error = Some(format!("crashed: {}", trap));
+                Controls::default()
+            }
+        }
+    }
+    fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+    fn debug(&mut self) -> DebugOutput {
+        ::std::mem::take(&mut self.store.data_mut().debug)
+    }
+    fn has_crashed(&self) -> bool {
+        self.crashed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    extern crate wat;
+
+    fn program(source: &str) -> WasmProgram {
+        WasmProgram::new(&wat::parse_str(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_fuel() {
+        let mut program = program(&r#"(module
+            (import "velox" "thrust" (func $thrust (param f32)))
+            (global $ticks (mut i32) (i32.const 0))
+            (func (export "tick")
+                (local $i i32)
+                (global.set $ticks (i32.add (global.get $ticks) (i32.const 1)))
+                ;; Costs more than a tick's fuel but less than what's left over from starting
+                (if (i32.eq (global.get $ticks) (i32.const 1))
+                    (then (loop
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br_if 0 (i32.lt_u (local.get $i) (i32.const LOOPS))))))
+                (if (i32.eq (global.get $ticks) (i32.const 2))
+                    (then (loop (br 0))))
+                (call $thrust (f32.const 1)))
+        )"#.replace("LOOPS", &(FUEL_PER_TICK / 2).to_string()));
+        let sensors = starting_sensors();
+
+        // The fuel left over from starting doesn't carry into the first tick
+        assert_eq!(program.control(&sensors), Controls::default());
+        assert_eq!(program.error(), Some("ran out of fuel".to_owned()));
+        assert_eq!(program.control(&sensors), Controls::default());
+        assert_eq!(program.error(), Some("ran out of fuel".to_owned()));
+        assert!(!program.has_crashed());
+
+        // It gets its fuel back for the next tick
+        assert_eq!(program.control(&sensors).thrust, 1.);
+        assert_eq!(program.error(), None);
+        assert_eq!(program.control(&sensors).thrust, 1.);
+    }
+
+    #[test]
+    fn memory_cant_grow_past_the_limit() {
+        let pages = |bytes: usize| bytes / 0x10000;
+        let mut program = program(&format!(r#"(module
+            (import "velox" "thrust" (func $thrust (param f32)))
+            (import "velox" "turn" (func $turn (param f32)))
+            (memory (export "memory") {})
+            (func (export "tick")
+                (call $thrust (f32.convert_i32_s (memory.grow (i32.const 2))))
+                (call $turn (f32.convert_i32_s (memory.grow (i32.const 1)))))
+        )"#, pages(MEMORY_LIMIT) - 1));
+
+        let controls = program.control(&starting_sensors());
+        assert_eq!(controls.thrust, -1.);
+        assert_eq!(controls.turn, (pages(MEMORY_LIMIT) - 1) as f32);
+        assert!(!program.has_crashed());
+
+        let source = format!("(module (memory {}) (func (export \"tick\")))", pages(MEMORY_LIMIT) + 1);
+        assert!(WasmProgram::new(&wat::parse_str(source).unwrap()).is_err());
+    }
+
+    #[test]
+    fn traps_crash_the_program() {
+        let mut program = program(r#"(module
+            (import "velox" "thrust" (func $thrust (param f32)))
+            (func (export "tick")
+                (call $thrust (f32.const 1))
+                unreachable)
+        )"#);
+        let sensors = starting_sensors();
+
+        assert_eq!(program.control(&sensors), Controls::default());
+        assert!(program.has_crashed());
+        assert!(program.error().unwrap().starts_with("crashed"));
+        assert_eq!(program.control(&sensors), Controls::default());
+    }
+}
@@ -0,0 +1,103 @@
+use damage::System;
+
+pub type WeaponId = u8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Behaviour {
+    /// Flies in a straight line
+    Straight,
+    /// Turns towards the closest enemy ship within `range`
+    Homing {
+        /// Radians per second
+        turn_rate: f32,
+        range: f32,
+    },
+    /// Stays where it is dropped
+    Mine,
+    /// Explodes on impact or when it runs out of time, damaging everything within `radius`
+    Bomb {
+        radius: f32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Weapon {
+    pub name: &'static str,
+    pub behaviour: Behaviour,
+    /// Speed relative to the ship firing it
+    pub speed: f32,
+    /// Seconds the projectile lives for
+    pub ttl: f32,
+    pub damage: u8,
+    /// Seconds before the weapon can be fired again
+    pub cooldown: f32,
+    /// System knocked out for some seconds when a hit gets through the shield
+    pub disables: Option<(System, f32)>,
+}
+
+pub const LASER: WeaponId = 0;
+pub const RAPID_LASER: WeaponId = 1;
+pub const MISSILE: WeaponId = 2;
+pub const MINE: WeaponId = 3;
+pub const BOMB: WeaponId = 4;
+
+pub const WEAPON_COUNT: usize = 5;
+
+/// Every weapon a ship has, indexed by `WeaponId`
+pub static WEAPONS: [Weapon; WEAPON_COUNT] = [
+    Weapon {
+        name: "laser",
+        behaviour: Behaviour::Straight,
+        speed: 400.,
+        ttl: 2.5,
+        damage: 1,
+        cooldown: 0.25,
+        disables: Some((System::Sensors, 1.)),
+    },
+    Weapon {
+        name: "rapid laser",
+        behaviour: Behaviour::Straight,
+        speed: 550.,
+        ttl: 1.2,
+        damage: 1,
+        cooldown: 0.08,
+        disables: None,
+    },
+    Weapon {
+        name: "missile",
+        behaviour: Behaviour::Homing {
+            turn_rate: 2.,
+            range: 400.,
+        },
+        speed: 180.,
+        ttl: 6.,
+        damage: 2,
+        cooldown: 2.,
+        disables: Some((System::Engines, 2.)),
+    },
+    Weapon {
+        name: "mine",
+        behaviour: Behaviour::Mine,
+        speed: 0.,
+        ttl: 30.,
+        damage: 3,
+        cooldown: 3.,
+        disables: Some((System::Engines, 3.)),
+    },
+    Weapon {
+        name: "bomb",
+        behaviour: Behaviour::Bomb {
+            radius: 120.,
+        },
+        speed: 150.,
+        ttl: 2.,
+        damage: 2,
+        cooldown: 4.,
+        disables: Some((System::Weapons, 2.)),
+    },
+];
+
+#[inline]
+pub fn weapon(id: WeaponId) -> Option<&'static Weapon> {
+    WEAPONS.get(id as usize)
+}
@@ -0,0 +1,620 @@
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use obj::{Vect, Vector2, PhysicsObject, Planet, Player, Laser, random_position, random_team_position, allies};
+use net::{Idx, TeamId, Target};
+use grid::SpatialGrid;
+use collision::sweep;
+use weapon::{Behaviour, WeaponId};
+use damage::System;
+
+pub const HIT_RADIUS: f32 = 32.;
+const SPAWN_CANDIDATES: usize = 16;
+
+/// Puts `elem` in the first free slot of `tree_map`
+#[inline]
+pub fn fit_in<T>(elem: T, tree_map: &mut BTreeMap<Idx, T>) -> Idx {
+    let idx = (0..).find(|i| !tree_map.contains_key(i)).unwrap();
+    let old = tree_map.insert(idx, elem);
+    debug_assert!(old.is_none());
+    idx
+}
+
+/// Takes out every projectile fired by player `owner`, returning their indexes
+///
+/// Indexes get reused, so ones left behind by a ship that left would count as the next one's.
+pub fn remove_lasers_of(owner: Idx, lasers: &mut BTreeMap<Idx, Laser>) -> Vec<Idx> {
+    let gone: Vec<Idx> = lasers.iter().filter(|&(_, l)| l.owner == owner).map(|(&i, _)| i).collect();
+    for i in &gone {
+        lasers.remove(i);
+    }
+    gone
+}
+
+/// Picks the spot furthest away from any living ship or planet out of some random ones
+///
+/// Ships on a team spawn in their team's part of the map.
+pub fn spawn_point<R: Rng>(rng: &mut R, players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, team: Option<TeamId>, teams: u8) -> Vect {
+    let obstacles: Vec<_> = players.values().filter(|p| p.is_alive()).map(|p| p.obj.pos())
+        .chain(planets.values().map(|p| p.obj.pos()))
+        .collect();
+
+    (0..SPAWN_CANDIDATES)
+        .map(|_| match team {
+            Some(t) if t < teams => random_team_position(rng, t, teams),
+            _ => random_position(rng),
+        })
+        .map(|p| (obstacles.iter().map(|&o| p.distance_to_squared(o)).fold(f32::INFINITY, f32::min), p))
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .unwrap().1
+}
+
+/// The planets every world starts out with
+pub fn starting_planets() -> BTreeMap<Idx, Planet> {
+    let planets = vec![
+        Planet::new(0., 0., 10., 2.),
+        Planet::new(50., 0., -10., 2.),
+        Planet::new(0., 0., 10., -2.),
+        Planet::new(0., 0., -10., -2.),
+        Planet::new(0., 400., 50., -20.),
+    ];
+    (0..).zip(planets).collect()
+}
+
+/// What a pilot can tell their ship to do
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Thrust(f32),
+    Rotate(f32),
+    Shoot(WeaponId),
+}
+
+impl Player {
+    /// Carries out `command` for the ship with id `i`, returning the projectile if one was fired
+    pub fn command(&mut self, i: Idx, command: Command) -> Option<Laser> {
+        if !self.is_alive() {
+            return None
+        }
+        match command {
+            Command::Thrust(a) => {
+                self.thrust = a;
+                if self.status.works(System::Engines) {
+                    self.obj.acceleration = a * Vector2::unit_vector(self.obj.rotation);
+                }
+                None
+            }
+            Command::Rotate(r) => {
+                self.obj.rotation += r;
+                None
+            }
+            Command::Shoot(weapon) => {
+                let laser = self.try_fire(i, weapon)?;
+                self.stats.shots_fired += 1;
+                Some(laser)
+            }
+        }
+    }
+}
+
+/// Settings that change how the world behaves
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Rules {
+    /// Number of teams, 0 if there are none
+    pub teams: u8,
+    /// Whether teammates can hurt each other
+    pub friendly_fire: bool,
+    /// Seconds a dead ship waits before respawning
+    pub respawn_time: f32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            teams: 0,
+            friendly_fire: false,
+            respawn_time: 3.,
+        }
+    }
+}
+
+/// Something that happened during a step that anyone watching might want to know about
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Respawned(Idx),
+    /// A projectile turned or wrapped around the map
+    LaserMoved(Idx),
+    PlanetMoved(Idx),
+    /// `target` was hit by a projectile fired by player `by`, taking `damage` to its hull
+    Damage {
+        target: Target,
+        by: Idx,
+        damage: u8,
+    },
+    /// A ship's status changed in a way its pilot should hear about
+    StatusChanged(Idx),
+    Died {
+        player: Idx,
+        killer: Option<Idx>,
+        pos: Vect,
+        respawn_in: Option<f32>,
+    },
+    PlanetsDestroyed(Vec<Idx>),
+    LasersGone(Vec<Idx>),
+}
+
+/// Steps ships, planets and projectiles forward, everything random comes from a seeded generator
+pub struct Simulation {
+    pub rules: Rules,
+    rng: XorShiftRng,
+    laser_grid: SpatialGrid<(Idx, Laser)>,
+    body_grid: SpatialGrid<Target>,
+}
+
+/// Generator that gives the same numbers for the same seed
+pub fn seeded_rng(seed: u32) -> XorShiftRng {
+    // XorShift must not be seeded with only zeroes
+    XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05])
+}
+
+impl Simulation {
+    pub fn new(rules: Rules, seed: u32) -> Self {
+        Simulation {
+            rules,
+            rng: seeded_rng(seed),
+            laser_grid: SpatialGrid::new(2. * HIT_RADIUS),
+            body_grid: SpatialGrid::new(4. * HIT_RADIUS),
+        }
+    }
+    /// Starts the random number generator over
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = seeded_rng(seed);
+    }
+    /// Brings ship `i` back somewhere out of the way
+    pub fn respawn(&mut self, players: &mut BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, i: Idx) {
+        let pos = spawn_point(&mut self.rng, players, planets, players[&i].team, self.rules.teams);
+        let rotation = self.rng.gen_range(-PI, PI);
+        players.get_mut(&i).unwrap().spawn(pos, rotation);
+    }
+    pub fn step(&mut self, players: &mut BTreeMap<Idx, Player>, planets: &mut BTreeMap<Idx, Planet>,
+                lasers: &mut BTreeMap<Idx, Laser>, delta: f32, events: &mut Vec<Event>) {
+        let mut dead_lasers = Vec::new();
+        let mut dead_planets = Vec::new();
+        let Rules{friendly_fire, respawn_time, ..} = self.rules;
+
+        let respawning: Vec<_> = players.iter()
+            .filter(|&(_, p)| p.respawn.map(|t| t <= 0.).unwrap_or(false))
+            .map(|(&i, _)| i)
+            .collect();
+        for i in respawning {
+            self.respawn(players, planets, i);
+            events.push(Event::Respawned(i));
+        }
+
+        self.body_grid.clear();
+        for (&i, planet) in planets.iter() {
+            self.body_grid.insert(Target::Planet(i), planet.obj.pos());
+        }
+        for (&i, player) in players.iter() {
+            if player.is_alive() {
+                self.body_grid.insert(Target::Player(i), player.obj.pos());
+            }
+        }
+
+        let mut max_travel = 0f32;
+        self.laser_grid.clear();
+        for (&l, laser) in lasers.iter_mut() {
+            if let Behaviour::Homing{range, ..} = laser.weapon().behaviour {
+                let pos = laser.obj.pos();
+                let mut closest = None;
+                self.body_grid.query(pos, range, |target, target_pos| match target {
+                    Target::Player(i) if i != laser.owner && (friendly_fire || !allies(laser.team, players[&i].team)) => {
+                        let dist = pos.distance_to_squared(target_pos);
+                        // Ties go to the lowest id so the grid's order doesn't matter
+                        if closest.map(|(d, j, _)| (dist, i) < (d, j)).unwrap_or(true) {
+                            closest = Some((dist, i, target_pos));
+                        }
+                    }
+                    _ => (),
+                });
+                if let Some((_, _, target_pos)) = closest {
+                    laser.steer_towards(target_pos, delta);
+                    events.push(Event::LaserMoved(l));
+                }
+            }
+            max_travel = max_travel.max(laser.obj.displacement(delta).length());
+            self.laser_grid.insert((l, *laser), laser.obj.pos());
+        }
+
+        // Every laser only hits the first thing in its path
+        let mut hits = Vec::new();
+        {
+            let laser_grid = &self.laser_grid;
+            let mut find_hits = |target: Target, team: Option<TeamId>, obj: &PhysicsObject| {
+                let reach = HIT_RADIUS + max_travel + obj.displacement(delta).length();
+                laser_grid.query_cells(obj.pos(), reach, |(l, laser), _| {
+                    // Lasers fly straight through teammates unless they can hurt them
+                    if target == Target::Player(laser.owner) || (!friendly_fire && allies(laser.team, team)) {
+                        return
+                    }
+                    if let Some(t) = sweep(&laser.obj, obj, HIT_RADIUS, delta) {
+                        hits.push((t, l, laser, target));
+                    }
+                });
+            };
+            for (&i, planet) in planets.iter() {
+                find_hits(Target::Planet(i), planet.team, &planet.obj);
+            }
+            for (&i, player) in players.iter() {
+                if player.is_alive() {
+                    find_hits(Target::Player(i), player.team, &player.obj);
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)).then(a.3.cmp(&b.3)));
+
+        let mut damage = Vec::new();
+        let mut explosions = Vec::new();
+        for (t, l, laser, target) in hits {
+            if dead_lasers.contains(&l) {
+                continue
+            }
+            dead_lasers.push(l);
+            if let Behaviour::Bomb{..} = laser.weapon().behaviour {
+                explosions.push((l, laser, laser.obj.pos() + laser.obj.displacement(t)));
+            } else {
+                damage.push((target, l, laser));
+            }
+        }
+
+        for l in dead_lasers.iter() {
+            lasers.remove(l);
+        }
+
+        for (&i, laser) in lasers.iter_mut() {
+            if laser.update(delta) {
+                dead_lasers.push(i);
+                if let Behaviour::Bomb{..} = laser.weapon().behaviour {
+                    explosions.push((i, *laser, laser.obj.pos()));
+                }
+            } else if laser.obj.stay_in_bounds() {
+                events.push(Event::LaserMoved(i));
+            }
+        }
+
+        for (l, laser, pos) in explosions {
+            if let Behaviour::Bomb{radius} = laser.weapon().behaviour {
+                let mut caught = Vec::new();
+                self.body_grid.query(pos, radius + HIT_RADIUS, |target, _| {
+                    let team = match target {
+                        Target::Planet(i) => planets[&i].team,
+                        Target::Player(i) => players[&i].team,
+                    };
+                    if target != Target::Player(laser.owner) && (friendly_fire || !allies(laser.team, team)) {
+                        caught.push(target);
+                    }
+                });
+                caught.sort();
+                damage.extend(caught.into_iter().map(|target| (target, l, laser)));
+            }
+        }
+
+        let mut hurt_players = Vec::new();
+        let mut killers = BTreeMap::new();
+        let mut landed = Vec::new();
+        for (target, l, laser) in damage {
+            let (done, was_dead, killed, friendly) = {
+                let (status, team) = match target {
+                    Target::Planet(i) => {
+                        let planet = planets.get_mut(&i).unwrap();
+                        (&mut planet.status, planet.team)
+                    }
+                    Target::Player(i) => {
+                        hurt_players.push(i);
+                        let player = players.get_mut(&i).unwrap();
+                        (&mut player.status, player.team)
+                    }
+                };
+                let was_dead = status.is_dead();
+                let done = status.hit(laser.damage, laser.weapon());
+                (done, was_dead, !was_dead && status.is_dead(), allies(laser.team, team))
+            };
+            if killed {
+                match target {
+                    Target::Planet(_) => (),
+                    Target::Player(i) => {
+                        killers.insert(i, laser.owner);
+                        players.get_mut(&i).unwrap().stats.deaths += 1;
+                    }
+                }
+            }
+            // The shooter might have left since firing, and wrecks don't count
+            if let Some(owner) = players.get_mut(&laser.owner).filter(|_| !was_dead) {
+                owner.stats.damage_dealt += done as u32;
+                if !landed.contains(&l) {
+                    landed.push(l);
+                    owner.stats.shots_hit += 1;
+                }
+                // Taking out your own side doesn't earn anything
+                if killed && !friendly {
+                    match target {
+                        Target::Planet(_) => owner.stats.planets_destroyed += 1,
+                        Target::Player(_) => owner.stats.kills += 1,
+                    }
+                }
+            }
+            events.push(Event::Damage {
+                target,
+                by: laser.owner,
+                damage: done,
+            });
+        }
+
+        for (&i, planet) in planets.iter_mut() {
+            if planet.status.is_dead() {
+                dead_planets.push(i)
+            } else {
+                planet.status.update(delta);
+                planet.obj.update(delta);
+
+                if planet.obj.stay_in_bounds() {
+                    events.push(Event::PlanetMoved(i));
+                }
+            }
+        }
+        for i in dead_planets.iter() {
+            planets.remove(i);
+        }
+
+        if !dead_planets.is_empty() {
+            events.push(Event::PlanetsDestroyed(dead_planets));
+        }
+
+        for (&i, player) in players.iter_mut() {
+            if !player.is_alive() {
+                player.update(delta);
+                continue
+            }
+            let before = player.status;
+
+            player.update(delta);
+            player.obj.stay_in_bounds();
+
+            if hurt_players.contains(&i) || player.status.noticeably_differs(&before) {
+                events.push(Event::StatusChanged(i));
+            }
+            if player.status.is_dead() {
+                let pos = player.obj.pos();
+                let respawn_in = player.kill(respawn_time);
+                events.push(Event::Died {
+                    player: i,
+                    killer: killers.get(&i).cloned(),
+                    pos,
+                    respawn_in,
+                });
+            }
+        }
+
+        if !dead_lasers.is_empty() {
+            for l in dead_lasers.iter() {
+                lasers.remove(l);
+            }
+            events.push(Event::LasersGone(dead_lasers));
+        }
+    }
+}
+
+/// Everything in a world at one moment
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub players: BTreeMap<Idx, Player>,
+    pub planets: BTreeMap<Idx, Planet>,
+    pub lasers: BTreeMap<Idx, Laser>,
+}
+
+/// A change to the world from outside the simulation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Input {
+    Command(Idx, Command),
+    /// A ship joined, as it was when it did
+    Join(Idx, Player),
+    Leave(Idx),
+    AddPlanet(Idx, Planet),
+}
+
+/// A simulation together with everything it simulates
+pub struct World {
+    pub players: BTreeMap<Idx, Player>,
+    pub planets: BTreeMap<Idx, Planet>,
+    pub lasers: BTreeMap<Idx, Laser>,
+    pub sim: Simulation,
+}
+
+impl World {
+    pub fn new(rules: Rules, seed: u32, snapshot: Snapshot) -> Self {
+        World {
+            players: snapshot.players,
+            planets: snapshot.planets,
+            lasers: snapshot.lasers,
+            sim: Simulation::new(rules, seed),
+        }
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            players: self.players.clone(),
+            planets: self.planets.clone(),
+            lasers: self.lasers.clone(),
+        }
+    }
+    pub fn apply(&mut self, input: &Input) {
+        match *input {
+            Input::Command(i, command) => {
+                let laser = self.players.get_mut(&i).and_then(|p| p.command(i, command));
+                if let Some(laser) = laser {
+                    fit_in(laser, &mut self.lasers);
+                }
+            }
+            Input::Join(i, player) => {
+                self.players.insert(i, player);
+            }
+            Input::Leave(i) => {
+                self.players.remove(&i);
+                remove_lasers_of(i, &mut self.lasers);
+            }
+            Input::AddPlanet(i, planet) => {
+                self.planets.insert(i, planet);
+            }
+        }
+    }
+    pub fn step(&mut self, delta: f32) -> Vec<Event> {
+        let mut events = Vec::new();
+        self.sim.step(&mut self.players, &mut self.planets, &mut self.lasers, delta, &mut events);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weapon::{LASER, MISSILE, MINE, BOMB};
+
+    /// Ships sitting still at `positions`, facing right
+    fn ships(positions: &[(f32, f32)]) -> BTreeMap<Idx, Player> {
+        (0..).zip(positions).map(|(i, &(x, y))| {
+            let mut player = Player::default();
+            player.spawn(Vector2(x, y), 0.);
+            (i, player)
+        }).collect()
+    }
+
+    #[test]
+    fn kills_count_the_health_that_was_left() {
+        let mut players = ships(&[(0., 0.), (-42., 0.)]);
+        {
+            let status = &mut players.get_mut(&1).unwrap().status;
+            status.shield = 0.;
+            status.health = 1;
+        }
+        // Dropped right on top of ship 1
+        let mut lasers = BTreeMap::new();
+        fit_in(Laser::fire(0, &players[&0].obj, MINE).unwrap(), &mut lasers);
+
+        let mut events = Vec::new();
+        Simulation::new(Rules::default(), 0).step(&mut players, &mut BTreeMap::new(), &mut lasers, 0.01, &mut events);
+
+        assert_eq!(players[&0].stats.damage_dealt, 1);
+        assert_eq!(players[&0].stats.kills, 1);
+        assert!(events.contains(&Event::Damage{target: Target::Player(1), by: 0, damage: 1}));
+        assert!(!players[&1].is_alive());
+    }
+
+    /// Steps for `seconds` in ticks of a hundredth of a second
+    fn run(rules: Rules, players: &mut BTreeMap<Idx, Player>, lasers: &mut BTreeMap<Idx, Laser>, seconds: f32) -> Vec<Event> {
+        let mut sim = Simulation::new(rules, 0);
+        let mut events = Vec::new();
+        for _ in 0..(seconds * 100.) as usize {
+            sim.step(players, &mut BTreeMap::new(), lasers, 0.01, &mut events);
+        }
+        events
+    }
+    fn hurt(events: &[Event], i: Idx) -> bool {
+        events.iter().any(|e| match *e {
+            Event::Damage{target, ..} => target == Target::Player(i),
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn missiles_turn_towards_enemies_in_range() {
+        // Fired to the right with an enemy below, well outside what it can turn in one step
+        let mut players = ships(&[(0., 0.), (0., 300.)]);
+        let mut lasers = BTreeMap::new();
+        let l = fit_in(Laser::fire(0, &players[&0].obj, MISSILE).unwrap(), &mut lasers);
+
+        let events = run(Rules::default(), &mut players, &mut lasers, 0.1);
+        let missile = &lasers[&l];
+        assert!((missile.obj.rotation - 2. * 0.1).abs() < 1e-4);
+        assert!((missile.obj.vel().direction() - missile.obj.rotation).abs() < 1e-4);
+        assert!(events.contains(&Event::LaserMoved(l)));
+
+        // A teammate or a ship out of range doesn't draw it off course
+        let mut players = ships(&[(0., 0.), (0., 300.), (0., -500.)]);
+        for player in players.values_mut().take(2) {
+            player.team = Some(0);
+        }
+        let mut missile = Laser::fire(0, &players[&0].obj, MISSILE).unwrap();
+        missile.team = Some(0);
+        let mut lasers = BTreeMap::new();
+        let l = fit_in(missile, &mut lasers);
+
+        run(Rules{teams: 2, .. Rules::default()}, &mut players, &mut lasers, 0.1);
+        assert_eq!(lasers[&l].obj.rotation, 0.);
+    }
+
+    #[test]
+    fn mines_dont_go_off_under_their_owner() {
+        let mut players = ships(&[(0., 0.), (300., 0.)]);
+        let mut lasers = BTreeMap::new();
+        let mine = Laser::fire(0, &players[&0].obj, MINE).unwrap();
+        let pos = mine.obj.pos();
+        let l = fit_in(mine, &mut lasers);
+        players.get_mut(&0).unwrap().spawn(pos, 0.);
+
+        let events = run(Rules::default(), &mut players, &mut lasers, 0.5);
+        assert_eq!(lasers[&l].obj.pos(), pos);
+        assert!(!hurt(&events, 0));
+        assert_eq!((players[&0].status.health, players[&0].status.shield), (10, 4.));
+    }
+
+    #[test]
+    fn bombs_hurt_everything_around_them_but_their_owner() {
+        // Just about to run out of time
+        let mut players = ships(&[(0., 0.), (40., 100.), (180., 0.), (40., -200.)]);
+        for player in players.values_mut() {
+            player.status.shield = 0.;
+        }
+        let mut bomb = Laser::fire(0, &players[&0].obj, BOMB).unwrap();
+        bomb.ttl = 0.005;
+        let mut lasers = BTreeMap::new();
+        fit_in(bomb, &mut lasers);
+
+        let events = run(Rules::default(), &mut players, &mut lasers, 0.01);
+        assert!(lasers.is_empty());
+        for (i, caught) in [(0, false), (1, true), (2, true), (3, false)] {
+            let status = &players[&i].status;
+            assert_eq!(hurt(&events, i), caught, "ship {}", i);
+            assert_eq!(status.health, if caught { 8 } else { 10 }, "ship {}", i);
+            assert_eq!(status.works(System::Weapons), !caught, "ship {}", i);
+        }
+        assert_eq!(players[&0].stats.damage_dealt, 4);
+        assert_eq!(players[&0].stats.shots_hit, 1);
+    }
+
+    #[test]
+    fn lasers_fly_through_teammates_without_friendly_fire() {
+        // A teammate in the line of fire with an enemy behind it
+        let setup = || {
+            let mut players = ships(&[(0., 0.), (150., 0.), (300., 0.)]);
+            for (i, player) in players.iter_mut() {
+                player.team = Some(if *i == 2 { 1 } else { 0 });
+            }
+            let mut laser = Laser::fire(0, &players[&0].obj, LASER).unwrap();
+            laser.team = Some(0);
+            let mut lasers = BTreeMap::new();
+            fit_in(laser, &mut lasers);
+            (players, lasers)
+        };
+
+        let (mut players, mut lasers) = setup();
+        let events = run(Rules{teams: 2, friendly_fire: false, .. Rules::default()}, &mut players, &mut lasers, 1.);
+        assert!(!hurt(&events, 1));
+        assert!(hurt(&events, 2));
+        assert_eq!((players[&1].status.health, players[&1].status.shield), (10, 4.));
+
+        let (mut players, mut lasers) = setup();
+        let events = run(Rules{teams: 2, friendly_fire: true, .. Rules::default()}, &mut players, &mut lasers, 1.);
+        assert!(hurt(&events, 1));
+        assert!(!hurt(&events, 2));
+    }
+}
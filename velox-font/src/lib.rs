@@ -0,0 +1,83 @@
+extern crate piston_window;
+
+use piston_window::*;
+
+/// 3x5 pixel glyphs, one bit per pixel row by row starting at the top left
+fn glyph(c: char) -> u16 {
+    match c {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        '-' => 0b000_000_111_000_000,
+        '%' => 0b101_001_010_100_101,
+        ':' => 0b000_010_000_010_000,
+        '.' => 0b000_000_000_000_010,
+        '/' => 0b001_001_010_100_100,
+        '!' => 0b010_010_010_000_010,
+        ',' => 0b000_000_000_010_100,
+        '\'' => 0b010_010_000_000_000,
+        '"' => 0b101_101_000_000_000,
+        '(' => 0b001_010_010_010_001,
+        ')' => 0b100_010_010_010_100,
+        '{' => 0b011_010_110_010_011,
+        '}' => 0b110_010_011_010_110,
+        '=' => 0b000_111_000_111_000,
+        '+' => 0b000_010_111_010_000,
+        '*' => 0b000_101_010_101_000,
+        '<' => 0b001_010_100_010_001,
+        '>' => 0b100_010_001_010_100,
+        '?' => 0b111_001_011_000_010,
+        '_' => 0b000_000_000_000_111,
+        '#' => 0b101_111_101_111_101,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b111_100_100_100_111,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b111_100_101_101_111,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_111,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b111_101_101_101_111,
+        'P' => 0b111_101_111_100_100,
+        'Q' => 0b111_101_101_111_001,
+        'R' => 0b110_101_110_101_101,
+        'S' => 0b111_100_111_001_111,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        _ => 0,
+    }
+}
+
+/// Draws `text` with its top left corner at `(x, y)`, each glyph pixel being `scale` wide
+///
+/// There are only capital letters, so small ones are drawn as capitals.
+pub fn text<G: Graphics>(text: &str, colour: [f32; 4], x: f64, y: f64, scale: f64, transform: math::Matrix2d, g: &mut G) {
+    for (n, c) in text.chars().enumerate() {
+        let bits = glyph(c.to_ascii_uppercase());
+        let gx = x + 4. * scale * n as f64;
+        for i in 0..15 {
+            if bits & (1 << (14 - i)) != 0 {
+                let (col, row) = (i % 3, i / 3);
+                rectangle(colour, [gx + scale * col as f64, y + scale * row as f64, scale, scale], transform, g);
+            }
+        }
+    }
+}
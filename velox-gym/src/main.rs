@@ -0,0 +1,155 @@
+extern crate velox_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use std::collections::BTreeMap;
+use std::env::args;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+
+use velox_core::net::{Idx, TeamId};
+use velox_core::control::{Controls, Sensors, load};
+use velox_core::training::{Environment, Settings, Step};
+
+/// A line sent by the trainer
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    Reset {
+        seed: u32,
+        /// Team the agents play on, if the match has teams
+        #[serde(default)]
+        team: Option<TeamId>,
+    },
+    Step {
+        #[serde(default)]
+        actions: BTreeMap<Idx, Controls>,
+    },
+}
+
+/// The line sent back for every request
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Response {
+    Reset {
+        observations: BTreeMap<Idx, Sensors>,
+    },
+    Step(Step),
+    Error {
+        error: String,
+    },
+}
+
+/// Answers one request per line from `input` until it ends
+fn serve<R: BufRead, W: Write>(env: &mut Environment, input: R, mut output: W) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue
+        }
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Reset{team: Some(team), ..}) if team >= env.settings.rules.teams => Response::Error {
+                error: format!("there's no team {} with {} teams", team, env.settings.rules.teams),
+            },
+            Ok(Request::Reset{seed, team}) => Response::Reset {
+                observations: env.reset(seed, team),
+            },
+            Ok(Request::Step{actions}) => Response::Step(env.step(&actions)),
+            Err(e) => Response::Error {
+                error: e.to_string(),
+            },
+        };
+        serde_json::to_writer(&mut output, &response).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        output.write_all(b"\n")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn parse<T: ::std::str::FromStr>(arg: &str, value: &str) -> T {
+    match value.parse() {
+        Ok(v) => v,
+        Err(_) => panic!("Invalid value {:?} for {}", value, arg),
+    }
+}
+
+fn main() {
+    let mut settings = Settings::default();
+    let mut opponents = Vec::new();
+    let mut listen = None;
+    let mut args = args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if let Err(e) = load(&arg) {
+                panic!("Couldn't load bot {:?}: {}", arg, e);
+            }
+            opponents.push(arg);
+            continue
+        }
+        let value = args.next();
+        match (&*arg, value.as_deref()) {
+            ("--agents", Some(v)) => settings.agents = parse(&arg, v),
+            ("--duration", Some(v)) => settings.duration = parse(&arg, v),
+            ("--tick", Some(v)) => settings.tick = parse(&arg, v),
+            ("--respawn-time", Some(v)) => settings.rules.respawn_time = parse(&arg, v),
+            ("--teams", Some(v)) => settings.rules.teams = parse(&arg, v),
+            ("--listen", Some(v)) => listen = Some(v.to_owned()),
+            _ => panic!("Unknown or incomplete option {}", arg),
+        }
+    }
+
+    let mut env = Environment::new(settings, move || opponents.iter().map(|name| load(name).unwrap()).collect());
+
+    match listen {
+        Some(addr) => {
+            let listener = TcpListener::bind(&*addr).unwrap();
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let input = BufReader::new(stream.try_clone().unwrap());
+                if let Err(e) = serve(&mut env, input, stream) {
+                    println!("Trainer disconnected: {}", e);
+                }
+            }
+        }
+        None => {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            serve(&mut env, stdin.lock(), stdout.lock()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velox_core::world::Rules;
+
+    #[test]
+    fn resets_put_agents_on_teams_that_exist() {
+        let settings = Settings {
+            duration: 0.1,
+            tick: 0.05,
+            rules: Rules{teams: 2, .. Rules::default()},
+            .. Settings::default()
+        };
+        let mut env = Environment::new(settings, || vec![load("idle").unwrap()]);
+        let input = concat!(
+            r#"{"reset": {"seed": 3, "team": 1}}"#, "\n",
+            r#"{"step": {"actions": {"0": {"thrust": 1}}}}"#, "\n",
+            r#"{"step": {}}"#, "\n",
+            r#"{"reset": {"seed": 3, "team": 2}}"#, "\n",
+        );
+        let mut output = Vec::new();
+        serve(&mut env, input.as_bytes(), &mut output).unwrap();
+
+        let lines: Vec<serde_json::Value> = output.split(|&b| b == b'\n').filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_slice(l).unwrap()).collect();
+        assert_eq!(lines[0]["observations"]["0"]["team"], 1);
+        assert_eq!(lines[1]["done"], false);
+        assert_eq!(lines[2]["done"], true);
+        assert!(lines[3]["error"].is_string());
+    }
+}
@@ -0,0 +1,57 @@
+extern crate piston_window;
+extern crate velox_core;
+extern crate velox_font as font;
+
+use piston_window::*;
+
+use velox_core::damage::Status;
+use velox_core::weapon::{WeaponId, WEAPON_COUNT};
+use velox_core::control::{DebugOutput, Shape};
+use velox_core::obj::Vect;
+
+/// Draws the health and shield bars, the chosen weapon and which systems are knocked out
+/// in the top left corner
+pub fn status<G: Graphics>(status: &Status, weapon: WeaponId, transform: math::Matrix2d, g: &mut G) {
+    let hp = status.health as f64 / status.max_health as f64;
+    let shield = if status.max_shield > 0. {status.shield as f64 / status.max_shield as f64} else {0.};
+    rectangle([0.77, 0.77, 0.77, 0.6], [0., 0., 170., 52.], transform, g);
+    rectangle([0., 1., 0., 0.6], [10., 5., 150.*hp, 14.], transform, g);
+    rectangle([0.2, 0.5, 1., 0.8], [10., 20., 150.*shield, 4.], transform, g);
+    for i in 0..WEAPON_COUNT {
+        let colour = if i == weapon as usize {[1., 1., 0., 0.8]} else {[0.3, 0.3, 0.3, 0.8]};
+        rectangle(colour, [10. + 20.*i as f64, 28., 16., 8.], transform, g);
+    }
+    for (i, &disabled) in status.disabled.iter().enumerate() {
+        let colour = if disabled > 0. {[1., 0., 0., 0.8]} else {[0., 0.6, 0., 0.8]};
+        rectangle(colour, [10. + 20.*i as f64, 40., 16., 6.], transform, g);
+    }
+}
+
+/// Draws what a ship program showed about a tick, `transform` putting world coordinates on screen
+///
+/// Shapes go where they are in the world, values and then the `log` lines next to the ship at `ship`.
+pub fn debug<'a, I, G>(debug: &DebugOutput, log: I, ship: Vect, transform: math::Matrix2d, g: &mut G)
+where I: IntoIterator<Item=&'a String>, G: Graphics {
+    let colour = [0.3, 0.9, 1., 0.8];
+    for shape in debug.shapes.iter() {
+        match *shape {
+            Shape::Line{from, to} => {
+                line(colour, 0.5, [from.0 as f64, from.1 as f64, to.0 as f64, to.1 as f64], transform, g);
+            }
+            Shape::Circle{pos, radius} => {
+                let (x, y, r) = (pos.0 as f64, pos.1 as f64, radius as f64);
+                Ellipse::new_border(colour, 0.5).draw([x - r, y - r, 2. * r, 2. * r], &Default::default(), transform, g);
+            }
+            Shape::Text{pos, ref text} => {
+                font::text(text, colour, pos.0 as f64, pos.1 as f64, 1.5, transform, g);
+            }
+        }
+    }
+
+    let (x, y) = (ship.0 as f64 + 24., ship.1 as f64 - 8.);
+    let values = debug.values.iter().map(|(name, v)| (format!("{} {:.2}", name, v), colour));
+    let log = log.into_iter().map(|l| (l.clone(), [0.8, 0.8, 0.8, 0.8]));
+    for (n, (line, colour)) in values.chain(log).enumerate() {
+        font::text(&line, colour, x, y + 10. * n as f64, 1.5, transform, g);
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use velox_core::control::{Sensors, Controls, DebugOutput, Shape, READINGS};
+use velox_core::obj::Vector2;
+
+use Error;
+use parser::{Program, Stmt, StmtKind, Expr, ExprKind, BinOp, UnOp};
+
+/// How deeply calls, blocks and expressions can nest between them while running
+///
+/// The parser keeps each function within limits, this stops functions calling each other from
+/// adding up to more than the stack holds.
+const MAX_DEPTH: usize = 256;
+
+/// Number of arguments every built-in function takes
+pub fn builtin_arity(name: &str) -> Option<usize> {
+    if let Some(&(_, indexed, _)) = READINGS.iter().find(|r| r.0 == name) {
+        return Some(indexed as usize);
+    }
+    Some(match name {
+        "pi" => 0,
+        "abs" | "sqrt" | "sin" | "cos" | "floor" | "thrust" | "turn" | "fire" | "log" => 1,
+        "atan2" | "min" | "max" | "value" => 2,
+        "circle" | "text" => 3,
+        "line" => 4,
+        _ => return None,
+    })
+}
+
+/// Which argument of builtin `name` is text in quotes, if any
+pub fn text_argument(name: &str) -> Option<usize> {
+    match name {
+        "log" | "value" => Some(0),
+        "text" => Some(2),
+        _ => None,
+    }
+}
+
+enum Flow {
+    Normal,
+    Return(f64),
+}
+
+pub struct Interpreter<'a> {
+    program: &'a Program,
+    globals: &'a mut HashMap<String, f64>,
+    /// Block scopes of the function being run, innermost last
+    scopes: Vec<HashMap<String, f64>>,
+    /// How deeply what's running is nested, calls, blocks and expressions alike
+    depth: usize,
+    budget: u64,
+    sensors: Option<&'a Sensors>,
+    pub controls: Controls,
+    pub debug: DebugOutput,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(program: &'a Program, globals: &'a mut HashMap<String, f64>, sensors: Option<&'a Sensors>, budget: u64) -> Self {
+        Interpreter {
+            program,
+            globals,
+            scopes: Vec::new(),
+            depth: 0,
+            budget,
+            sensors,
+            controls: Controls::default(),
+            debug: DebugOutput::default(),
+        }
+    }
+    /// Runs statements outside of any function
+    pub fn run_top(&mut self) -> Result<(), Error> {
+        let program = self.program;
+        self.block(&program.top).map(|_| ())
+    }
+    pub fn call(&mut self, name: &str, args: Vec<f64>, line: usize) -> Result<f64, Error> {
+        let function = match self.program.functions.get(name) {
+            Some(f) => f,
+            None => return self.builtin(name, &args, None, line),
+        };
+        if args.len() != function.params.len() {
+            return Err(Error::new(line, format!("{} takes {} arguments, not {}", name, function.params.len(), args.len())));
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::new(line, "functions call each other too deeply".to_owned()));
+        }
+
+        let frame = vec![function.params.iter().cloned().zip(args).collect()];
+        let outer = ::std::mem::replace(&mut self.scopes, frame);
+        self.depth += 1;
+        let result = self.block(&function.body);
+        self.depth -= 1;
+        self.scopes = outer;
+
+        match result? {
+            Flow::Return(v) => Ok(v),
+            Flow::Normal => Ok(0.),
+        }
+    }
+    fn charge(&mut self, line: usize) -> Result<(), Error> {
+        if self.budget == 0 {
+            return Err(Error::new(line, "ran out of instructions for this tick".to_owned()));
+        }
+        self.budget -= 1;
+        Ok(())
+    }
+    /// Goes one level deeper for what's on `line`, unless that's too deep
+    fn nest(&mut self, line: usize) -> Result<(), Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::new(line, "this is nested too deeply to run".to_owned()));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+    fn block(&mut self, stmts: &[Stmt]) -> Result<Flow, Error> {
+        let top = self.scopes.is_empty();
+        if !top {
+            self.nest(stmts.first().map_or(0, |s| s.line))?;
+            self.scopes.push(HashMap::new());
+        }
+        let mut flow = Ok(Flow::Normal);
+        for stmt in stmts {
+            flow = self.statement(stmt);
+            match flow {
+                Ok(Flow::Normal) => (),
+                _ => break,
+            }
+        }
+        if !top {
+            self.scopes.pop();
+            self.depth -= 1;
+        }
+        flow
+    }
+    fn variable(&mut self, name: &str) -> Option<&mut f64> {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(v) = scope.get_mut(name) {
+                return Some(v)
+            }
+        }
+        self.globals.get_mut(name)
+    }
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow, Error> {
+        self.charge(stmt.line)?;
+        match stmt.kind {
+            StmtKind::Let(ref name, ref expr) => {
+                let value = self.expr(expr)?;
+                match self.scopes.last_mut() {
+                    Some(scope) => scope.insert(name.clone(), value),
+                    None => self.globals.insert(name.clone(), value),
+                };
+            }
+            StmtKind::Assign(ref name, ref expr) => {
+                let value = self.expr(expr)?;
+                match self.variable(name) {
+                    Some(v) => *v = value,
+                    None => return Err(Error::new(stmt.line, format!("{} isn't defined, use let to define it", name))),
+                }
+            }
+            StmtKind::If(ref branches, ref otherwise) => {
+                for (condition, block) in branches {
+                    if self.expr(condition)? != 0. {
+                        return self.block(block);
+                    }
+                }
+                return self.block(otherwise);
+            }
+            StmtKind::While(ref condition, ref block) => {
+                while self.expr(condition)? != 0. {
+                    if let Flow::Return(v) = self.block(block)? {
+                        return Ok(Flow::Return(v));
+                    }
+                }
+            }
+            StmtKind::For(ref name, ref from, ref to, ref block) => {
+                let from = self.expr(from)?;
+                let to = self.expr(to)?;
+                let mut i = from;
+                while i < to {
+                    self.charge(stmt.line)?;
+                    self.scopes.push(Some((name.clone(), i)).into_iter().collect());
+                    let flow = self.block(block);
+                    self.scopes.pop();
+                    if let Flow::Return(v) = flow? {
+                        return Ok(Flow::Return(v));
+                    }
+                    i += 1.;
+                }
+            }
+            StmtKind::Return(ref expr) => {
+                let value = match *expr {
+                    Some(ref expr) => self.expr(expr)?,
+                    None => 0.,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Expr(ref expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+    fn expr(&mut self, expr: &Expr) -> Result<f64, Error> {
+        self.charge(expr.line)?;
+        self.nest(expr.line)?;
+        let value = self.evaluate(expr);
+        self.depth -= 1;
+        value
+    }
+    fn evaluate(&mut self, expr: &Expr) -> Result<f64, Error> {
+        Ok(match expr.kind {
+            ExprKind::Number(n) => n,
+            ExprKind::Str(_) => return Err(Error::new(expr.line, "text can only be passed to log, value and text".to_owned())),
+            ExprKind::Var(ref name) => match self.variable(name) {
+                Some(&mut v) => v,
+                None => return Err(Error::new(expr.line, format!("{} isn't defined", name))),
+            },
+            ExprKind::Unary(op, ref e) => {
+                let v = self.expr(e)?;
+                match op {
+                    UnOp::Neg => -v,
+                    UnOp::Not => truth(v == 0.),
+                }
+            }
+            ExprKind::Binary(BinOp::And, ref a, ref b) => truth(self.expr(a)? != 0. && self.expr(b)? != 0.),
+            ExprKind::Binary(BinOp::Or, ref a, ref b) => truth(self.expr(a)? != 0. || self.expr(b)? != 0.),
+            ExprKind::Binary(op, ref a, ref b) => {
+                let a = self.expr(a)?;
+                let b = self.expr(b)?;
+                match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => a / b,
+                    BinOp::Rem => a % b,
+                    BinOp::Eq => truth(a == b),
+                    BinOp::Ne => truth(a != b),
+                    BinOp::Lt => truth(a < b),
+                    BinOp::Le => truth(a <= b),
+                    BinOp::Gt => truth(a > b),
+                    BinOp::Ge => truth(a >= b),
+                    BinOp::And | BinOp::Or => unreachable!(),
+                }
+            }
+            ExprKind::Call(ref name, ref args) if text_argument(name).is_some() => {
+                let mut text = String::new();
+                let mut values = Vec::new();
+                for arg in args {
+                    match arg.kind {
+                        ExprKind::Str(ref t) => text = t.clone(),
+                        _ => values.push(self.expr(arg)?),
+                    }
+                }
+                self.builtin(name, &values, Some(text), expr.line)?
+            }
+            ExprKind::Call(ref name, ref args) => {
+                let args = args.iter().map(|a| self.expr(a)).collect::<Result<_, _>>()?;
+                self.call(name, args, expr.line)?
+            }
+        })
+    }
+    /// Runs builtin `name`, with the text argument left out of `args` for those that take one
+    fn builtin(&mut self, name: &str, args: &[f64], text: Option<String>, line: usize) -> Result<f64, Error> {
+        let given = args.len() + text.is_some() as usize;
+        match builtin_arity(name) {
+            Some(n) if n == given => (),
+            Some(n) => return Err(Error::new(line, format!("{} takes {} arguments, not {}", name, n, given))),
+            None => return Err(Error::new(line, format!("there's no function called {}", name))),
+        }
+        let point = |x: f64, y: f64| Vector2(x as f32, y as f32);
+        let arg = args.first().cloned().unwrap_or(0.);
+        let i = if arg >= 0. { arg as usize } else { usize::MAX };
+
+        let value = match name {
+            "pi" => return Ok(PI),
+            "abs" => return Ok(arg.abs()),
+            "sqrt" => return Ok(arg.sqrt()),
+            "sin" => return Ok(arg.sin()),
+            "cos" => return Ok(arg.cos()),
+            "floor" => return Ok(arg.floor()),
+            "atan2" => return Ok(args[0].atan2(args[1])),
+            "min" => return Ok(args[0].min(args[1])),
+            "max" => return Ok(args[0].max(args[1])),
+            "thrust" => {
+                self.controls.thrust = arg as f32;
+                return Ok(0.)
+            }
+            "turn" => {
+                self.controls.turn = arg as f32;
+                return Ok(0.)
+            }
+            "fire" => {
+                self.controls.fire = if (0. ..256.).contains(&arg) { Some(arg as u8) } else { None };
+                return Ok(0.)
+            }
+            "log" => {
+                self.debug.log(text.unwrap_or_default());
+                return Ok(0.)
+            }
+            "value" => {
+                self.debug.value(text.unwrap_or_default(), arg as f32);
+                return Ok(0.)
+            }
+            "line" => {
+                self.debug.shape(Shape::Line {
+                    from: point(args[0], args[1]),
+                    to: point(args[2], args[3]),
+                });
+                return Ok(0.)
+            }
+            "circle" => {
+                self.debug.shape(Shape::Circle {
+                    pos: point(args[0], args[1]),
+                    radius: args[2] as f32,
+                });
+                return Ok(0.)
+            }
+            "text" => {
+                self.debug.shape(Shape::Text {
+                    pos: point(args[0], args[1]),
+                    text: text.unwrap_or_default(),
+                });
+                return Ok(0.)
+            }
+            _ => {
+                let s = match self.sensors {
+                    Some(s) => s,
+                    None => return Err(Error::new(line, format!("{} can only be read during a tick", name))),
+                };
+                match s.get(name, i) {
+                    Some(value) => value,
+                    None => unreachable!(),
+                }
+            }
+        };
+        Ok(value as f64)
+    }
+}
+
+fn truth(b: bool) -> f64 {
+    if b { 1. } else { 0. }
+}
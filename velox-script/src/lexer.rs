@@ -0,0 +1,150 @@
+use Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Let,
+    Fn,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Assign,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    DotDot,
+}
+
+/// Splits `source` into tokens, each with the line it's on
+pub fn lex(source: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                continue
+            }
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => return Err(Error::new(line, "text isn't closed with \"".to_owned())),
+                        Some(c) => text.push(c),
+                    }
+                }
+                Token::Str(text)
+            }
+            '0'..='9' => {
+                let mut number = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '_' {
+                        number.push(c);
+                    } else if c == '.' && !number.contains('.') {
+                        // Could be the start of a range instead
+                        let mut ahead = chars.clone();
+                        ahead.next();
+                        if !ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                            break
+                        }
+                        number.push(c);
+                    } else {
+                        break
+                    }
+                    chars.next();
+                }
+                match number.replace('_', "").parse() {
+                    Ok(n) => Token::Number(n),
+                    Err(_) => return Err(Error::new(line, format!("invalid number {}", number))),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                match &*word {
+                    "let" => Token::Let,
+                    "fn" => Token::Fn,
+                    "if" => Token::If,
+                    "else" => Token::Else,
+                    "while" => Token::While,
+                    "for" => Token::For,
+                    "in" => Token::In,
+                    "return" => Token::Return,
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Number(1.),
+                    "false" => Token::Number(0.),
+                    _ => Token::Ident(word),
+                }
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            ',' => Token::Comma,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '=' | '!' | '<' | '>' | '.' => {
+                let double = chars.peek() == Some(&if c == '.' { '.' } else { '=' });
+                if double {
+                    chars.next();
+                }
+                match (c, double) {
+                    ('=', false) => Token::Assign,
+                    ('=', true) => Token::Eq,
+                    ('!', true) => Token::Ne,
+                    ('<', false) => Token::Lt,
+                    ('<', true) => Token::Le,
+                    ('>', false) => Token::Gt,
+                    ('>', true) => Token::Ge,
+                    ('.', true) => Token::DotDot,
+                    _ => return Err(Error::new(line, format!("unexpected {:?}", c))),
+                }
+            }
+            c => return Err(Error::new(line, format!("unexpected {:?}", c))),
+        };
+        tokens.push((token, line));
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,238 @@
+extern crate velox_core;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use velox_core::control::{ShipController, Sensors, Controls, DebugOutput};
+
+mod lexer;
+mod parser;
+mod interp;
+
+use parser::{Program, Stmt, StmtKind, Expr, ExprKind};
+use interp::{Interpreter, builtin_arity, text_argument};
+
+/// Instructions a script gets to run every tick
+pub const INSTRUCTIONS_PER_TICK: u64 = 10_000;
+/// Instructions a script gets to run when it's loaded
+pub const START_INSTRUCTIONS: u64 = 10 * INSTRUCTIONS_PER_TICK;
+
+/// Something wrong with a script and the line it's on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error {
+    fn new(line: usize, message: String) -> Self {
+        Error {
+            line,
+            message,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// Checks that every function called exists and gets the right number of arguments, with text
+/// only where it's taken
+fn check_calls(program: &Program, stmts: &[Stmt]) -> Result<(), Error> {
+    fn expr(program: &Program, e: &Expr) -> Result<(), Error> {
+        match e.kind {
+            ExprKind::Number(_) | ExprKind::Var(_) => Ok(()),
+            ExprKind::Str(_) => Err(Error::new(e.line, "text can only be passed to log, value and text".to_owned())),
+            ExprKind::Unary(_, ref e) => expr(program, e),
+            ExprKind::Binary(_, ref a, ref b) => expr(program, a).and_then(|()| expr(program, b)),
+            ExprKind::Call(ref name, ref args) => {
+                let arity = match program.functions.get(name) {
+                    Some(f) => f.params.len(),
+                    None => match builtin_arity(name) {
+                        Some(n) => n,
+                        None => return Err(Error::new(e.line, format!("there's no function called {}", name))),
+                    }
+                };
+                if arity != args.len() {
+                    return Err(Error::new(e.line, format!("{} takes {} arguments, not {}", name, arity, args.len())));
+                }
+                let text = program.functions.get(name).map_or_else(|| text_argument(name), |_| None);
+                args.iter().enumerate().try_for_each(|(i, a)| match (Some(i) == text, &a.kind) {
+                    (true, &ExprKind::Str(_)) => Ok(()),
+                    (true, _) => Err(Error::new(a.line, format!("{} takes text in quotes as argument {}", name, i + 1))),
+                    (false, _) => expr(program, a),
+                })
+            }
+        }
+    }
+    for stmt in stmts {
+        match stmt.kind {
+            StmtKind::Let(_, ref e) | StmtKind::Assign(_, ref e) | StmtKind::Expr(ref e) => expr(program, e)?,
+            StmtKind::Return(ref e) => if let Some(ref e) = *e {
+                expr(program, e)?
+            },
+            StmtKind::If(ref branches, ref otherwise) => {
+                for (condition, block) in branches {
+                    expr(program, condition)?;
+                    check_calls(program, block)?;
+                }
+                check_calls(program, otherwise)?;
+            }
+            StmtKind::While(ref condition, ref block) => {
+                expr(program, condition)?;
+                check_calls(program, block)?;
+            }
+            StmtKind::For(_, ref from, ref to, ref block) => {
+                expr(program, from)?;
+                expr(program, to)?;
+                check_calls(program, block)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A ship program in the Velox scripting language
+///
+/// Statements outside functions are run once when it's loaded and the variables they define
+/// keep their values between ticks. The `tick` function is called every tick.
+pub struct Script {
+    program: Program,
+    globals: HashMap<String, f64>,
+    last_error: Option<Error>,
+    debug: DebugOutput,
+}
+
+impl Script {
+    /// Compiles `source` and runs its top level
+    pub fn new(source: &str) -> Result<Self, Error> {
+        let program = parser::parse(lexer::lex(source)?)?;
+        match program.functions.get("tick") {
+            Some(f) if !f.params.is_empty() => return Err(Error::new(f.line, "tick can't take any arguments".to_owned())),
+            Some(_) => (),
+            None => return Err(Error::new(1, "there's no tick function".to_owned())),
+        }
+        check_calls(&program, &program.top)?;
+        for function in program.functions.values() {
+            check_calls(&program, &function.body)?;
+        }
+
+        let mut globals = HashMap::new();
+        Interpreter::new(&program, &mut globals, None, START_INSTRUCTIONS).run_top()?;
+        Ok(Script {
+            program,
+            globals,
+            last_error: None,
+            debug: DebugOutput::default(),
+        })
+    }
+    /// Runs `tick` with `sensors` and returns what it told the ship to do
+    pub fn tick(&mut self, sensors: &Sensors) -> Result<Controls, Error> {
+        let mut interpreter = Interpreter::new(&self.program, &mut self.globals, Some(sensors), INSTRUCTIONS_PER_TICK);
+        let result = interpreter.call("tick", Vec::new(), 0);
+        self.debug = ::std::mem::take(&mut interpreter.debug);
+        result.map(|_| interpreter.controls)
+    }
+    /// What went wrong during the last tick, if anything
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
+}
+
+impl ShipController for Script {
+    /// Does nothing for a tick that fails
+    fn control(&mut self, sensors: &Sensors) -> Controls {
+        match self.tick(sensors) {
+            Ok(controls) => {
+                self.last_error = None;
+                controls
+            }
+            Err(e) => {
+                self.last_error = Some(e);
+                Controls::default()
+            }
+        }
+    }
+    fn error(&self) -> Option<String> {
+        self.last_error.as_ref().map(Error::to_string)
+    }
+    fn debug(&mut self) -> DebugOutput {
+        ::std::mem::take(&mut self.debug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velox_core::arena::Arena;
+
+    fn sensors() -> Sensors {
+        let mut arena = Arena::new(Default::default(), 1);
+        let i = arena.join(None);
+        arena.sense(i)
+    }
+
+    fn tick_error(source: &str) -> Error {
+        Script::new(source).unwrap().tick(&sensors()).unwrap_err()
+    }
+
+    #[test]
+    fn unclosed_text_is_a_lex_error() {
+        let e = Script::new("fn tick() {\n  log(\"chasing)\n}").err().unwrap();
+        assert_eq!(e, Error::new(2, "text isn't closed with \"".to_owned()));
+    }
+
+    #[test]
+    fn undefined_variables_say_where() {
+        let e = tick_error("fn tick() {\n  let a = 1\n  thrust(a + b)\n}");
+        assert_eq!(e, Error::new(3, "b isn't defined".to_owned()));
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_instructions() {
+        let mut script = Script::new("let n = 0\nfn tick() {\n  while true {\n    n = n + 1\n  }\n}").unwrap();
+        let e = script.tick(&sensors()).unwrap_err();
+        assert_eq!(e.message, "ran out of instructions for this tick");
+        // The next tick gets a fresh budget and the variables keep what they got to
+        let n = script.globals["n"];
+        assert!(n > 0.);
+        assert!(script.tick(&sensors()).is_err());
+        assert!(script.globals["n"] > n);
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = |open: &str, close: &str| format!("fn tick() {{\n  thrust({}1{})\n}}", open.repeat(5000), close.repeat(5000));
+        let blocks = format!("fn tick() {{\n  {}{}\n}}", "if 1 { ".repeat(5000), "}".repeat(5000));
+        for source in &[deep("(", ")"), deep("-", ""), deep("not ", ""), deep("1 + ", ""), deep("abs(", ")"), blocks] {
+            let e = Script::new(source).err().unwrap();
+            assert_eq!(e, Error::new(2, "this is nested too deeply".to_owned()));
+        }
+    }
+
+    #[test]
+    fn deep_calls_stop_before_the_stack_runs_out() {
+        // Every call in the middle of something as deeply nested as it can be
+        let negated = format!("fn f(n) {{\n  if n <= 0 {{ return 0 }}\n  return {}f(n - 1)\n}}\nfn tick() {{\n  thrust(f(1000))\n}}", "-".repeat(60));
+        let blocks = format!("fn f(n) {{\n  {}if n > 0 {{ return f(n - 1) }}{}\n  return 0\n}}\nfn tick() {{\n  thrust(f(1000))\n}}", "if 1 { ".repeat(60), "}".repeat(60));
+        for source in &[negated, blocks] {
+            let e = tick_error(source);
+            assert!(e.message.contains("too deeply"), "{}", e);
+        }
+        let factorial = "fn f(n) {\n  if n <= 1 { return 1 }\n  return n * f(n - 1)\n}\nfn tick() {\n  thrust(f(50))\n}";
+        assert!(Script::new(factorial).unwrap().tick(&sensors()).is_ok());
+    }
+
+    #[test]
+    fn wrong_number_of_arguments() {
+        let e = Script::new("fn f(a, b) {\n  return a + b\n}\nfn tick() {\n  turn(f(1))\n}").err().unwrap();
+        assert_eq!(e, Error::new(5, "f takes 2 arguments, not 1".to_owned()));
+        let e = Script::new("fn tick() {\n\n  thrust(abs(1, 2))\n}").err().unwrap();
+        assert_eq!(e, Error::new(3, "abs takes 1 arguments, not 2".to_owned()));
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use Error;
+use lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Number(f64),
+    /// Text in quotes, which only some builtins take
+    Str(String),
+    Var(String),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Let(String, Expr),
+    Assign(String, Expr),
+    /// Every condition with its block, then the `else` block
+    If(Vec<(Expr, Vec<Stmt>)>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    /// Counts from the first up to but not including the second
+    For(String, Expr, Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+    pub line: usize,
+}
+
+/// A whole parsed script
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// Statements outside any function, run once when the script is loaded
+    pub top: Vec<Stmt>,
+    pub functions: HashMap<String, Function>,
+}
+
+/// Deepest blocks, brackets and operators can nest
+///
+/// Everything working on the parsed program recurses, so this keeps scripts from overflowing the
+/// stack of whatever runs them.
+const MAX_NESTING: usize = 64;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// How deeply what's being parsed is nested
+    depth: usize,
+}
+
+pub fn parse(tokens: Vec<(Token, usize)>) -> Result<Program, Error> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let mut program = Program::default();
+
+    while parser.peek().is_some() {
+        if parser.eat(&Token::Fn) {
+            let line = parser.line();
+            let name = parser.ident()?;
+            parser.expect(Token::LParen)?;
+            let mut params = Vec::new();
+            if !parser.eat(&Token::RParen) {
+                loop {
+                    params.push(parser.ident()?);
+                    if parser.eat(&Token::RParen) {
+                        break
+                    }
+                    parser.expect(Token::Comma)?;
+                }
+            }
+            let body = parser.block()?;
+            if program.functions.insert(name.clone(), Function{params, body, line}).is_some() {
+                return Err(Error::new(line, format!("function {} is defined twice", name)));
+            }
+        } else {
+            program.top.push(parser.statement()?);
+        }
+    }
+    Ok(program)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+    /// Line of the next token, or the last one at the end
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or_else(|| self.tokens.last()).map_or(1, |t| t.1)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|t| t.0.clone());
+        self.pos += 1;
+        token
+    }
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn unexpected(&self, wanted: &str) -> Error {
+        match self.peek() {
+            Some(t) => Error::new(self.line(), format!("expected {}, found {}", wanted, describe(t))),
+            None => Error::new(self.line(), format!("expected {}, found the end", wanted)),
+        }
+    }
+    fn expect(&mut self, token: Token) -> Result<(), Error> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&describe(&token)))
+        }
+    }
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(&Token::Ident(_)) => match self.next() {
+                Some(Token::Ident(name)) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+    /// Goes one level deeper, unless that's too deep
+    fn nest(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            Err(Error::new(self.line(), "this is nested too deeply".to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+    fn block(&mut self) -> Result<Vec<Stmt>, Error> {
+        self.expect(Token::LBrace)?;
+        self.nest()?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Token::RBrace) {
+            if self.peek().is_none() {
+                return Err(self.unexpected("}"));
+            }
+            stmts.push(self.statement()?);
+        }
+        self.depth -= 1;
+        Ok(stmts)
+    }
+    fn statement(&mut self) -> Result<Stmt, Error> {
+        let line = self.line();
+        let kind = match self.peek() {
+            Some(&Token::Let) => {
+                self.next();
+                let name = self.ident()?;
+                self.expect(Token::Assign)?;
+                StmtKind::Let(name, self.expr()?)
+            }
+            Some(&Token::If) => {
+                self.next();
+                let mut branches = vec![(self.expr()?, self.block()?)];
+                let mut otherwise = Vec::new();
+                while self.eat(&Token::Else) {
+                    if self.eat(&Token::If) {
+                        branches.push((self.expr()?, self.block()?));
+                    } else {
+                        otherwise = self.block()?;
+                        break
+                    }
+                }
+                StmtKind::If(branches, otherwise)
+            }
+            Some(&Token::While) => {
+                self.next();
+                StmtKind::While(self.expr()?, self.block()?)
+            }
+            Some(&Token::For) => {
+                self.next();
+                let name = self.ident()?;
+                self.expect(Token::In)?;
+                let from = self.expr()?;
+                self.expect(Token::DotDot)?;
+                let to = self.expr()?;
+                StmtKind::For(name, from, to, self.block()?)
+            }
+            Some(&Token::Return) => {
+                self.next();
+                match self.peek() {
+                    Some(&Token::RBrace) | None => StmtKind::Return(None),
+                    _ => StmtKind::Return(Some(self.expr()?)),
+                }
+            }
+            Some(&Token::Ident(_)) if self.tokens.get(self.pos + 1).map(|t| &t.0) == Some(&Token::Assign) => {
+                let name = self.ident()?;
+                self.next();
+                StmtKind::Assign(name, self.expr()?)
+            }
+            _ => StmtKind::Expr(self.expr()?),
+        };
+        Ok(Stmt {
+            kind,
+            line,
+        })
+    }
+    fn expr(&mut self) -> Result<Expr, Error> {
+        self.binary(0)
+    }
+    /// Parses operators binding at least as tightly as `level`
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+        const LEVELS: &[&[(Token, BinOp)]] = &[
+            &[(Token::Or, BinOp::Or)],
+            &[(Token::And, BinOp::And)],
+            &[(Token::Eq, BinOp::Eq), (Token::Ne, BinOp::Ne), (Token::Lt, BinOp::Lt),
+              (Token::Le, BinOp::Le), (Token::Gt, BinOp::Gt), (Token::Ge, BinOp::Ge)],
+            &[(Token::Plus, BinOp::Add), (Token::Minus, BinOp::Sub)],
+            &[(Token::Star, BinOp::Mul), (Token::Slash, BinOp::Div), (Token::Percent, BinOp::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        // Every operator in a chain puts what came before it a level deeper
+        let depth = self.depth;
+        'outer: loop {
+            for &(ref token, op) in LEVELS[level] {
+                if self.peek() == Some(token) {
+                    let line = self.line();
+                    self.next();
+                    self.nest()?;
+                    let right = self.binary(level + 1)?;
+                    left = Expr {
+                        kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+                        line,
+                    };
+                    continue 'outer
+                }
+            }
+            self.depth = depth;
+            return Ok(left)
+        }
+    }
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let line = self.line();
+        let op = match self.peek() {
+            Some(&Token::Minus) => UnOp::Neg,
+            Some(&Token::Not) => UnOp::Not,
+            _ => return self.primary(),
+        };
+        self.next();
+        self.nest()?;
+        let operand = self.unary()?;
+        self.depth -= 1;
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(operand)),
+            line,
+        })
+    }
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let line = self.line();
+        let kind = match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.next();
+                ExprKind::Number(n)
+            }
+            Some(Token::Str(text)) => {
+                self.next();
+                ExprKind::Str(text)
+            }
+            Some(Token::Ident(name)) => {
+                self.next();
+                if self.eat(&Token::LParen) {
+                    self.nest()?;
+                    let mut args = Vec::new();
+                    if !self.eat(&Token::RParen) {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.eat(&Token::RParen) {
+                                break
+                            }
+                            self.expect(Token::Comma)?;
+                        }
+                    }
+                    self.depth -= 1;
+                    ExprKind::Call(name, args)
+                } else {
+                    ExprKind::Var(name)
+                }
+            }
+            Some(Token::LParen) => {
+                self.next();
+                self.nest()?;
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                self.depth -= 1;
+                return Ok(expr)
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        Ok(Expr {
+            kind,
+            line,
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Number(n) => n.to_string(),
+        Token::Str(ref text) => format!("{:?}", text),
+        Token::Ident(ref name) => name.clone(),
+        Token::Let => "let".to_owned(),
+        Token::Fn => "fn".to_owned(),
+        Token::If => "if".to_owned(),
+        Token::Else => "else".to_owned(),
+        Token::While => "while".to_owned(),
+        Token::For => "for".to_owned(),
+        Token::In => "in".to_owned(),
+        Token::Return => "return".to_owned(),
+        Token::And => "and".to_owned(),
+        Token::Or => "or".to_owned(),
+        Token::Not => "not".to_owned(),
+        Token::LParen => "(".to_owned(),
+        Token::RParen => ")".to_owned(),
+        Token::LBrace => "{".to_owned(),
+        Token::RBrace => "}".to_owned(),
+        Token::Comma => ",".to_owned(),
+        Token::Assign => "=".to_owned(),
+        Token::Eq => "==".to_owned(),
+        Token::Ne => "!=".to_owned(),
+        Token::Lt => "<".to_owned(),
+        Token::Le => "<=".to_owned(),
+        Token::Gt => ">".to_owned(),
+        Token::Ge => ">=".to_owned(),
+        Token::Plus => "+".to_owned(),
+        Token::Minus => "-".to_owned(),
+        Token::Star => "*".to_owned(),
+        Token::Slash => "/".to_owned(),
+        Token::Percent => "%".to_owned(),
+        Token::DotDot => "..".to_owned(),
+    }
+}
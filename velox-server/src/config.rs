@@ -0,0 +1,165 @@
+use std::env::args;
+
+use velox_core::net::ModeKind;
+
+/// Longest room name in bytes, so the rooms are always listed
+pub const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Name of the room
+    pub name: String,
+    /// Players the room takes at most
+    pub max_players: usize,
+    /// Spectators the room takes at most, they don't count towards `max_players`
+    pub max_spectators: usize,
+    pub mode: ModeKind,
+    /// Number of teams in team modes
+    pub teams: u8,
+    /// Points needed to win, if any
+    pub score_limit: Option<i32>,
+    /// Seconds a dead ship waits before respawning
+    pub respawn_time: f32,
+    /// Lives each player gets, unlimited if `None`
+    pub lives: Option<u8>,
+    /// Seconds before a match ends, endless if `None`
+    pub time_limit: Option<f32>,
+    /// File match results are appended to, stdout if `None`
+    pub results: Option<String>,
+    /// Whether teammates can hurt each other
+    pub friendly_fire: bool,
+    /// Folder a replay of every match is saved to, if any
+    pub record: Option<String>,
+    /// Players needed before a match will start
+    pub min_players: usize,
+    /// Seconds of practice before a match
+    pub warmup_time: f32,
+    /// Seconds ships are frozen before a match starts
+    pub countdown_time: f32,
+    /// Seconds the results are shown before the next match
+    pub results_time: f32,
+    /// Seconds every tick simulates when running as fast as possible instead of in real time
+    ///
+    /// Nothing is broadcast to clients then, only results and replays come out of the room.
+    pub virtual_clock: Option<f32>,
+    /// Ships there should be in all while anyone is playing, bots fill in for missing players
+    pub fill_to: usize,
+    /// What flies the fill-in bots, anything `velox_core::control::load` takes
+    pub fill_bot: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            name: "default".to_owned(),
+            max_players: 16,
+            max_spectators: 8,
+            mode: ModeKind::Deathmatch,
+            teams: 2,
+            score_limit: None,
+            respawn_time: 3.,
+            lives: None,
+            time_limit: None,
+            results: None,
+            friendly_fire: false,
+            record: None,
+            min_players: 2,
+            warmup_time: 10.,
+            countdown_time: 5.,
+            results_time: 10.,
+            virtual_clock: None,
+            fill_to: 0,
+            fill_bot: "gunner".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the rooms to host from options of the form `--name value` on the command line
+    ///
+    /// `--room name` starts a new room and `--rooms n` adds `n` numbered ones.
+    /// Options before any of them apply to every room, options after them only to the last room.
+    pub fn from_args() -> Vec<Self> {
+        let mut defaults = Config::default();
+        let mut rooms: Vec<Config> = Vec::new();
+        let mut args = args().skip(1);
+
+        while let Some(arg) = args.next() {
+            let value = args.next();
+            match (&*arg, value.as_deref()) {
+                ("--room", Some(v)) => {
+                    rooms.push(Config {
+                        name: v.to_owned(),
+                        .. defaults.clone()
+                    });
+                    continue
+                }
+                ("--rooms", Some(v)) => {
+                    for n in 0..parse::<usize>(&arg, v) {
+                        rooms.push(Config {
+                            name: format!("{}-{}", defaults.name, n + 1),
+                            .. defaults.clone()
+                        });
+                    }
+                    continue
+                }
+                _ => (),
+            }
+            let config = rooms.last_mut().unwrap_or(&mut defaults);
+            match (&*arg, value.as_deref()) {
+                ("--name", Some(v)) => config.name = v.to_owned(),
+                ("--max-players", Some(v)) => config.max_players = parse(&arg, v),
+                ("--max-spectators", Some(v)) => config.max_spectators = parse(&arg, v),
+                ("--mode", Some(v)) => config.mode = parse_mode(v),
+                ("--teams", Some(v)) => config.teams = parse(&arg, v),
+                ("--score-limit", Some(v)) => config.score_limit = Some(parse(&arg, v)),
+                ("--respawn-time", Some(v)) => config.respawn_time = parse(&arg, v),
+                ("--lives", Some(v)) => config.lives = Some(parse(&arg, v)),
+                ("--time-limit", Some(v)) => config.time_limit = Some(parse(&arg, v)),
+                ("--results", Some(v)) => config.results = Some(v.to_owned()),
+                ("--friendly-fire", Some(v)) => config.friendly_fire = parse(&arg, v),
+                ("--record", Some(v)) => config.record = Some(v.to_owned()),
+                ("--min-players", Some(v)) => config.min_players = parse(&arg, v),
+                ("--warmup-time", Some(v)) => config.warmup_time = parse(&arg, v),
+                ("--countdown-time", Some(v)) => config.countdown_time = parse(&arg, v),
+                ("--results-time", Some(v)) => config.results_time = parse(&arg, v),
+                ("--virtual-clock", Some(v)) => config.virtual_clock = Some(parse(&arg, v)),
+                ("--fill-to", Some(v)) => config.fill_to = parse(&arg, v),
+                ("--fill-bot", Some(v)) => config.fill_bot = v.to_owned(),
+                _ => panic!("Unknown or incomplete option {}", arg),
+            }
+        }
+
+        if rooms.is_empty() {
+            rooms.push(defaults);
+        }
+        for room in &rooms {
+            if room.name.len() > MAX_NAME_LENGTH {
+                panic!("Room name {:?} is longer than {} bytes", room.name, MAX_NAME_LENGTH);
+            }
+            // With fewer there is no other team to play against, planet defence would be over at once
+            let team_mode = room.mode == ModeKind::TeamDeathmatch || room.mode == ModeKind::PlanetDefence;
+            if team_mode && room.teams < 2 {
+                panic!("{:?} in room {} needs at least 2 teams, not {}", room.mode, room.name, room.teams);
+            }
+        }
+        rooms
+    }
+}
+
+fn parse_mode(value: &str) -> ModeKind {
+    match value {
+        "deathmatch" => ModeKind::Deathmatch,
+        "team-deathmatch" => ModeKind::TeamDeathmatch,
+        "last-ship-standing" => ModeKind::LastShipStanding,
+        "planet-defence" => ModeKind::PlanetDefence,
+        _ => panic!("Unknown game mode {:?}", value),
+    }
+}
+
+fn parse<T: ::std::str::FromStr>(arg: &str, value: &str) -> T {
+    match value.parse() {
+        Ok(v) => v,
+        Err(_) => panic!("Invalid value {:?} for {}", value, arg),
+    }
+}
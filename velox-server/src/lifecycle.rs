@@ -0,0 +1,47 @@
+use velox_core::net::{ServerPacket, MatchPhase};
+
+pub struct Lifecycle {
+    pub phase: MatchPhase,
+    /// Seconds left of the current phase if it's timed
+    pub time_left: Option<f32>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Lifecycle {
+            phase: MatchPhase::Waiting,
+            time_left: None,
+        }
+    }
+    pub fn enter(&mut self, phase: MatchPhase, time: Option<f32>) {
+        self.phase = phase;
+        self.time_left = time;
+    }
+    /// Counts down the current phase and returns whether its time is up
+    pub fn tick(&mut self, dt: f32) -> bool {
+        match self.time_left {
+            Some(ref mut t) => {
+                *t -= dt;
+                *t <= 0.
+            }
+            None => false,
+        }
+    }
+    /// Whether ships can move and shoot
+    pub fn allows_input(&self) -> bool {
+        match self.phase {
+            MatchPhase::Waiting | MatchPhase::Warmup | MatchPhase::Running => true,
+            MatchPhase::Countdown | MatchPhase::Results(_) => false,
+        }
+    }
+    /// Whether players joining now have to wait for the next match to get a ship
+    pub fn queues_joins(&self) -> bool {
+        matches!(self.phase, MatchPhase::Countdown | MatchPhase::Running)
+    }
+    pub fn packet(&self) -> ServerPacket {
+        ServerPacket::Phase {
+            phase: self.phase,
+            time_left: self.time_left,
+        }
+    }
+}
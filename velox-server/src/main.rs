@@ -1,8 +1,17 @@
 extern crate velox_core;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 mod serv;
+mod config;
+mod results;
+mod mode;
+mod lifecycle;
+mod rooms;
 
 fn main() {
-    serv::Server::new().run()
+    rooms::Lobby::new(config::Config::from_args()).run()
 }
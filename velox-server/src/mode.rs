@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+
+use velox_core::net::{Idx, TeamId, ModeKind, Outcome};
+use velox_core::obj::{Planet, Player};
+use velox_core::score::Stats;
+
+use config::Config;
+
+/// Rules of a match
+pub trait GameMode: Send {
+    fn kind(&self) -> ModeKind;
+    /// How many teams players are split into, 0 if it's every ship for itself
+    fn teams(&self) -> u8 {
+        0
+    }
+    fn lives(&self) -> Option<u8>;
+    fn time_limit(&self) -> Option<f32>;
+    fn score(&self, stats: &Stats) -> i32 {
+        self.kind().score(stats)
+    }
+    /// Team a newly joined player should be on
+    fn assign_team(&self, players: &BTreeMap<Idx, Player>) -> Option<TeamId> {
+        smallest_team(self.teams(), players)
+    }
+    /// Prepares the world at the start of every match, with the `players` taking part
+    fn setup(&mut self, _players: &BTreeMap<Idx, Player>, _planets: &mut BTreeMap<Idx, Planet>) {}
+    /// Whether matches can end at all, without a score or time limit a deathmatch goes on forever
+    fn can_end(&self) -> bool {
+        true
+    }
+    /// Decides whether the match is over
+    fn outcome(&self, players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, time: f32) -> Option<Outcome>;
+}
+
+pub fn from_config(config: &Config) -> Box<dyn GameMode> {
+    match config.mode {
+        ModeKind::Deathmatch => Box::new(Deathmatch {
+            score_limit: config.score_limit,
+            time_limit: config.time_limit,
+            lives: config.lives,
+        }),
+        ModeKind::TeamDeathmatch => Box::new(TeamDeathmatch {
+            teams: config.teams,
+            score_limit: config.score_limit,
+            time_limit: config.time_limit,
+            lives: config.lives,
+        }),
+        ModeKind::LastShipStanding => Box::new(LastShipStanding {
+            time_limit: config.time_limit,
+            lives: config.lives.unwrap_or(1),
+            contenders: 0,
+        }),
+        ModeKind::PlanetDefence => Box::new(PlanetDefence {
+            teams: config.teams,
+            time_limit: config.time_limit,
+            lives: config.lives,
+        }),
+    }
+}
+
+fn smallest_team(teams: u8, players: &BTreeMap<Idx, Player>) -> Option<TeamId> {
+    (0..teams).min_by_key(|&t| players.values().filter(|p| p.team == Some(t)).count())
+}
+
+fn time_up(limit: Option<f32>, time: f32) -> bool {
+    limit.map(|limit| time >= limit).unwrap_or(false)
+}
+
+/// The one with the highest score wins, unless it's shared
+fn best<K: Copy, I: Iterator<Item=(K, i32)>>(scores: I) -> Option<K> {
+    let mut best = None;
+    let mut tied = false;
+    for (k, score) in scores {
+        match best {
+            Some((_, s)) if s > score => (),
+            Some((_, s)) if s == score => tied = true,
+            _ => {
+                best = Some((k, score));
+                tied = false;
+            }
+        }
+    }
+    if tied {
+        None
+    } else {
+        best.map(|(k, _)| k)
+    }
+}
+
+fn team_scores<M: GameMode + ?Sized>(mode: &M, players: &BTreeMap<Idx, Player>) -> Vec<(TeamId, i32)> {
+    (0..mode.teams()).map(|t| (t, players.values()
+        .filter(|p| p.team == Some(t))
+        .map(|p| mode.score(&p.stats))
+        .sum()))
+        .collect()
+}
+
+/// Every ship for itself, most points wins
+pub struct Deathmatch {
+    score_limit: Option<i32>,
+    time_limit: Option<f32>,
+    lives: Option<u8>,
+}
+
+impl GameMode for Deathmatch {
+    fn kind(&self) -> ModeKind {
+        ModeKind::Deathmatch
+    }
+    fn can_end(&self) -> bool {
+        self.score_limit.is_some() || self.time_limit.is_some()
+    }
+    fn lives(&self) -> Option<u8> {
+        self.lives
+    }
+    fn time_limit(&self) -> Option<f32> {
+        self.time_limit
+    }
+    fn outcome(&self, players: &BTreeMap<Idx, Player>, _: &BTreeMap<Idx, Planet>, time: f32) -> Option<Outcome> {
+        let scores = players.iter().map(|(&i, p)| (i, self.score(&p.stats)));
+        if let Some(limit) = self.score_limit {
+            if let Some((i, _)) = scores.clone().find(|&(_, s)| s >= limit) {
+                return Some(Outcome::Player(i))
+            }
+        }
+        if time_up(self.time_limit, time) {
+            Some(best(scores).map(Outcome::Player).unwrap_or(Outcome::Draw))
+        } else {
+            None
+        }
+    }
+}
+
+/// Teams pool their points
+pub struct TeamDeathmatch {
+    teams: u8,
+    score_limit: Option<i32>,
+    time_limit: Option<f32>,
+    lives: Option<u8>,
+}
+
+impl GameMode for TeamDeathmatch {
+    fn kind(&self) -> ModeKind {
+        ModeKind::TeamDeathmatch
+    }
+    fn can_end(&self) -> bool {
+        self.score_limit.is_some() || self.time_limit.is_some()
+    }
+    fn teams(&self) -> u8 {
+        self.teams
+    }
+    fn lives(&self) -> Option<u8> {
+        self.lives
+    }
+    fn time_limit(&self) -> Option<f32> {
+        self.time_limit
+    }
+    fn outcome(&self, players: &BTreeMap<Idx, Player>, _: &BTreeMap<Idx, Planet>, time: f32) -> Option<Outcome> {
+        let scores = team_scores(self, players);
+        if let Some(limit) = self.score_limit {
+            if let Some(&(t, _)) = scores.iter().find(|&&(_, s)| s >= limit) {
+                return Some(Outcome::Team(t))
+            }
+        }
+        if time_up(self.time_limit, time) {
+            Some(best(scores.into_iter()).map(Outcome::Team).unwrap_or(Outcome::Draw))
+        } else {
+            None
+        }
+    }
+}
+
+/// Limited lives, the last ship with any left wins
+pub struct LastShipStanding {
+    time_limit: Option<f32>,
+    lives: u8,
+    /// Ships the current match started with
+    contenders: usize,
+}
+
+impl GameMode for LastShipStanding {
+    fn kind(&self) -> ModeKind {
+        ModeKind::LastShipStanding
+    }
+    fn lives(&self) -> Option<u8> {
+        Some(self.lives)
+    }
+    fn time_limit(&self) -> Option<f32> {
+        self.time_limit
+    }
+    fn setup(&mut self, players: &BTreeMap<Idx, Player>, _: &mut BTreeMap<Idx, Planet>) {
+        self.contenders = players.len();
+    }
+    fn outcome(&self, players: &BTreeMap<Idx, Player>, _: &BTreeMap<Idx, Planet>, time: f32) -> Option<Outcome> {
+        // Nobody has won a fight against nobody, but once it's started leaving is losing
+        if self.contenders < 2 {
+            return None
+        }
+        let mut standing = players.iter().filter(|&(_, p)| !p.is_out());
+        match (standing.next(), standing.next()) {
+            (None, _) => Some(Outcome::Draw),
+            (Some((&i, _)), None) => Some(Outcome::Player(i)),
+            _ if time_up(self.time_limit, time) => {
+                let lives = players.iter().filter(|&(_, p)| !p.is_out())
+                    .map(|(&i, p)| (i, p.lives.unwrap_or(0) as i32));
+                Some(best(lives).map(Outcome::Player).unwrap_or(Outcome::Draw))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Every team has a home planet, a team loses when theirs is destroyed
+pub struct PlanetDefence {
+    teams: u8,
+    time_limit: Option<f32>,
+    lives: Option<u8>,
+}
+
+const HOME_PLANET_HEALTH: u8 = 40;
+const HOME_PLANET_DISTANCE: f32 = 400.;
+
+impl GameMode for PlanetDefence {
+    fn kind(&self) -> ModeKind {
+        ModeKind::PlanetDefence
+    }
+    fn teams(&self) -> u8 {
+        self.teams
+    }
+    fn lives(&self) -> Option<u8> {
+        self.lives
+    }
+    fn time_limit(&self) -> Option<f32> {
+        self.time_limit
+    }
+    fn setup(&mut self, _: &BTreeMap<Idx, Player>, planets: &mut BTreeMap<Idx, Planet>) {
+        planets.clear();
+        for t in 0..self.teams {
+            let angle = 2. * ::std::f32::consts::PI * t as f32 / self.teams as f32;
+            let (y, x) = angle.sin_cos();
+            let mut home = Planet::new(HOME_PLANET_DISTANCE * x, 0.75 * HOME_PLANET_DISTANCE * y, 0., 0.);
+            home.status.health = HOME_PLANET_HEALTH;
+            home.status.max_health = HOME_PLANET_HEALTH;
+            home.team = Some(t);
+            planets.insert(t as Idx, home);
+        }
+    }
+    fn outcome(&self, _: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, time: f32) -> Option<Outcome> {
+        let homes: Vec<_> = planets.values().filter_map(|p| p.team.map(|t| (t, p.status.health as i32))).collect();
+        match homes.len() {
+            0 => Some(Outcome::Draw),
+            1 => Some(Outcome::Team(homes[0].0)),
+            _ if time_up(self.time_limit, time) => {
+                Some(best(homes.into_iter()).map(Outcome::Team).unwrap_or(Outcome::Draw))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_ship_standing(players: &BTreeMap<Idx, Player>) -> LastShipStanding {
+        let mut mode = LastShipStanding {
+            time_limit: None,
+            lives: 1,
+            contenders: 0,
+        };
+        mode.setup(players, &mut BTreeMap::new());
+        mode
+    }
+
+    #[test]
+    fn leaving_last_ship_standing_loses() {
+        let mut players: BTreeMap<Idx, Player> = (0..2).map(|i| (i, Player::default())).collect();
+        let planets = BTreeMap::new();
+        let mode = last_ship_standing(&players);
+        assert_eq!(mode.outcome(&players, &planets, 1.), None);
+
+        players.remove(&0);
+        assert_eq!(mode.outcome(&players, &planets, 1.), Some(Outcome::Player(1)));
+        players.remove(&1);
+        assert_eq!(mode.outcome(&players, &planets, 1.), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn last_ship_standing_needs_a_fight() {
+        let mut players = BTreeMap::new();
+        players.insert(0, Player::default());
+        let mode = last_ship_standing(&players);
+        assert_eq!(mode.outcome(&players, &BTreeMap::new(), 1.), None);
+    }
+}
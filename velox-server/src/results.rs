@@ -0,0 +1,64 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+
+use serde_json;
+
+use velox_core::net::{Idx, TeamId, ModeKind, Outcome};
+use velox_core::score::Stats;
+
+#[derive(Debug, Serialize)]
+pub struct PlayerResult {
+    pub id: Idx,
+    /// `None` for fill-in bots
+    pub address: Option<SocketAddr>,
+    pub team: Option<TeamId>,
+    pub score: i32,
+    pub accuracy: f32,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+/// Summary of a finished match, best player first
+#[derive(Debug, Serialize)]
+pub struct MatchResult {
+    /// Name of the room it was played in
+    pub room: String,
+    pub mode: ModeKind,
+    pub outcome: Outcome,
+    /// Seconds the match lasted
+    pub duration: f32,
+    pub players: Vec<PlayerResult>,
+}
+
+impl MatchResult {
+    /// Takes every player's id, address, team, score and stats
+    pub fn new<I>(room: &str, mode: ModeKind, outcome: Outcome, duration: f32, players: I) -> Self
+    where I: IntoIterator<Item=(Idx, Option<SocketAddr>, Option<TeamId>, i32, Stats)> {
+        let mut players: Vec<_> = players.into_iter().map(|(id, address, team, score, stats)| PlayerResult {
+            id,
+            address,
+            team,
+            score,
+            accuracy: stats.accuracy(),
+            stats,
+        }).collect();
+        players.sort_by(|a, b| b.score.cmp(&a.score).then(a.stats.deaths.cmp(&b.stats.deaths)));
+
+        MatchResult {
+            room: room.to_owned(),
+            mode,
+            outcome,
+            duration,
+            players,
+        }
+    }
+    /// Writes the result as a line of JSON, appending to `path` or to stdout if there is none
+    pub fn emit(&self, path: Option<&str>) -> io::Result<()> {
+        let line = serde_json::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match path {
+            Some(path) => writeln!(OpenOptions::new().create(true).append(true).open(path)?, "{}", line),
+            None => writeln!(io::stdout(), "{}", line),
+        }
+    }
+}
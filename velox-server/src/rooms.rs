@@ -0,0 +1,145 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::thread;
+
+use velox_core::net::*;
+
+use config::Config;
+use serv::{Server, Handle};
+
+/// Hosts every room on one socket and passes packets on to the room their sender is in
+pub struct Lobby {
+    server_socket: Arc<ServerSocket>,
+    rooms: Vec<Handle>,
+    servers: Vec<Server>,
+    /// Room each connected address is in
+    routes: HashMap<SocketAddr, RoomId>,
+}
+
+impl Lobby {
+    pub fn new(configs: Vec<Config>) -> Self {
+        Self::on(Arc::new(ServerSocket::new((Ipv4Addr::new(0, 0, 0, 0), 7351))), configs)
+    }
+    fn on(server_socket: Arc<ServerSocket>, configs: Vec<Config>) -> Self {
+        let servers: Vec<_> = configs.into_iter().enumerate()
+            .map(|(id, config)| Server::new(id as RoomId, config, server_socket.clone()))
+            .collect();
+
+        Lobby {
+            rooms: servers.iter().map(Server::handle).collect(),
+            servers,
+            server_socket,
+            routes: HashMap::new(),
+        }
+    }
+    fn send_rooms(&self, remote: &SocketAddr) {
+        for packet in rooms_packets(self.rooms.iter().map(Handle::info).collect()) {
+            if let Err(e) = self.server_socket.send(packet, remote) {
+                println!("Couldn't send rooms to {}: {}", remote, e);
+                return
+            }
+        }
+    }
+    pub fn run(mut self) {
+        for server in self.servers.drain(..) {
+            thread::spawn(move || server.run());
+        }
+
+        loop {
+            let (remote, packet) = self.server_socket.recv().unwrap();
+            self.route(remote, packet);
+        }
+    }
+    /// Deals with `packet` or passes it on to the room `remote` is in
+    fn route(&mut self, remote: SocketAddr, packet: ClientPacket) {
+        match packet {
+            ClientPacket::ListRooms => self.send_rooms(&remote),
+            ClientPacket::Connect | ClientPacket::JoinRoom(_) if !self.routes.contains_key(&remote) => {
+                let room = match packet {
+                    ClientPacket::JoinRoom(id) => self.rooms.get(id as usize).filter(|r| !r.is_full()),
+                    _ => self.rooms.iter().find(|r| !r.is_full()),
+                };
+                match room {
+                    Some(room) => {
+                        self.routes.insert(remote, room.id);
+                        room.handle(remote, ClientPacket::Connect);
+                    }
+                    None => self.send_rooms(&remote),
+                }
+            }
+            ClientPacket::Spectate(id) if !self.routes.contains_key(&remote) => {
+                match self.rooms.get(id as usize).filter(|r| r.has_spectator_room()) {
+                    Some(room) => {
+                        self.routes.insert(remote, id);
+                        room.handle(remote, packet);
+                    }
+                    None => self.send_rooms(&remote),
+                }
+            }
+            ClientPacket::Disconnect => match self.routes.remove(&remote) {
+                Some(id) => self.rooms[id as usize].handle(remote, packet),
+                None => if let Err(e) = self.server_socket.send(ServerPacket::DisconnectAck, &remote) {
+                    println!("Couldn't acknowledge {} disconnecting: {}", remote, e);
+                },
+            },
+            // Already in a room, so it's been resent or duplicated on the way
+            ClientPacket::Connect | ClientPacket::JoinRoom(_) | ClientPacket::Spectate(_) => (),
+            packet => if let Some(&id) = self.routes.get(&remote) {
+                self.rooms[id as usize].handle(remote, packet);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    #[test]
+    fn connecting_twice_makes_one_ship() {
+        let mut lobby = Lobby::on(Arc::new(ServerSocket::new("127.0.0.1:0")), vec![Config::default()]);
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let remote = client.local_addr().unwrap();
+
+        lobby.route(remote, ClientPacket::Connect);
+        lobby.route(remote, ClientPacket::Connect);
+        lobby.route(remote, ClientPacket::JoinRoom(0));
+        assert_eq!(lobby.rooms[0].ships(), 1);
+        assert_eq!(lobby.rooms[0].info().players, 1);
+
+        lobby.route(remote, ClientPacket::Spectate(0));
+        assert_eq!(lobby.rooms[0].info().spectators, 0);
+
+        lobby.route(remote, ClientPacket::Disconnect);
+        assert_eq!(lobby.rooms[0].ships(), 0);
+        assert_eq!(lobby.rooms[0].info().players, 0);
+    }
+
+    #[test]
+    fn rooms_with_long_names_are_all_listed() {
+        let configs = (0..40).map(|n| Config {
+            name: format!("{:02} {}", n, "x".repeat(60)),
+            .. Config::default()
+        }).collect();
+        let mut lobby = Lobby::on(Arc::new(ServerSocket::new("127.0.0.1:0")), configs);
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+
+        lobby.route(client.local_addr().unwrap(), ClientPacket::ListRooms);
+        let mut buf = [0; 2048];
+        let mut listed = 0;
+        while let Ok(size) = client.recv(&mut buf) {
+            assert!(size <= 1024);
+            // Each name shows up in one packet
+            listed += buf[..size].windows(3).filter(|w| w[2] == b' ' && w[0].is_ascii_digit() && w[1].is_ascii_digit()).count();
+        }
+        assert_eq!(listed, 40);
+
+        // Nothing can be sent to port 0, which mustn't take the lobby down
+        lobby.route("127.0.0.1:0".parse().unwrap(), ClientPacket::ListRooms);
+        lobby.route("127.0.0.1:0".parse().unwrap(), ClientPacket::Disconnect);
+    }
+}
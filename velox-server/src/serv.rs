@@ -1,233 +1,858 @@
-use std::net::{Ipv4Addr, SocketAddr};
-use std::time::{Instant, Duration};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
-use std::collections::{HashMap, BTreeMap};
 use std::thread;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::f32::consts::PI;
 
 use velox_core::net::*;
-use velox_core::obj::{Vector2, RotatableObject, Planet, Player};
+use velox_core::obj::{Planet, Player, Laser};
+use velox_core::world::{Simulation, Rules, Event, Command, Input, Snapshot, fit_in, remove_lasers_of, spawn_point, starting_planets};
+use velox_core::replay::{Recorder, Header, REPLAY_VERSION};
+use velox_core::clock::Clock;
+use velox_core::control::{Pilot, Radar, load};
 
+use rand::{thread_rng, Rng};
+
+use config::Config;
+use results::MatchResult;
+use mode::{self, GameMode};
+use lifecycle::Lifecycle;
+
+/// One room, a world with its own match going on
 pub struct Server {
+    id: RoomId,
+    config: Config,
+    mode: Arc<Mutex<Box<dyn GameMode>>>,
+    lifecycle: Arc<Mutex<Lifecycle>>,
     planets: Arc<Mutex<BTreeMap<Idx, Planet>>>,
     server_socket: Arc<ServerSocket>,
     connections: Arc<Mutex<HashMap<SocketAddr, Idx>>>,
+    /// Addresses watching the room without a ship
+    spectators: Arc<Mutex<HashSet<SocketAddr>>>,
+    /// Addresses of players who want the room's fill-in bot to fly their ship
+    autopilot: Arc<Mutex<HashSet<SocketAddr>>>,
     players: Arc<Mutex<BTreeMap<Idx, Player>>>,
-    deads: Vec<SocketAddr>,
-    lasers: Arc<Mutex<BTreeMap<Idx, RotatableObject>>>
+    lasers: Arc<Mutex<BTreeMap<Idx, Laser>>>,
+    sim: Simulation,
+    /// Replay of the match being played, if it's being recorded
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    /// Matches played so far
+    matches: u32,
+    /// Seconds since the match started
+    match_time: f32,
+    /// Ships flown by bots filling in for missing players
+    fill_ins: BTreeMap<Idx, Pilot>,
+    /// Players' ships flown by the room's fill-in bot for them
+    autopilots: BTreeMap<Idx, Pilot>,
+}
+
+/// How many players' stats fit in one scoreboard packet
+const SCOREBOARD_CHUNK: usize = 32;
+/// Pause between ticks with a real clock, which is also how long fill-in bots get to think
+const TICK_PAUSE: Duration = Duration::from_millis(18);
+/// Frames between full snapshots in replays, about a second
+const KEYFRAME_INTERVAL: u32 = 55;
+
+/// Adds `input` to the replay if one is being recorded
+fn record(recorder: &Mutex<Option<Recorder>>, input: Input) {
+    if let Some(ref mut recorder) = *recorder.lock().unwrap() {
+        recorder.input(input);
+    }
 }
 
 #[inline]
-fn remove_player(socket: &ServerSocket, connections: &mut HashMap<SocketAddr, Idx>, players: &mut BTreeMap<Idx, Player>, dead: SocketAddr) {
+fn remove_player(socket: &ServerSocket, connections: &mut HashMap<SocketAddr, Idx>, spectators: &HashSet<SocketAddr>, players: &mut BTreeMap<Idx, Player>, lasers: &mut BTreeMap<Idx, Laser>, dead: SocketAddr) {
     socket.send(ServerPacket::DisconnectAck, &dead).unwrap();
 
     if let Some(dead_id) = connections.remove(&dead) {
         players.remove(&dead_id);
-        socket.send_all(ServerPacket::DeletePlayer(dead_id), connections.keys()).unwrap();
+        socket.send_all(ServerPacket::DeletePlayer(dead_id), connections.keys().chain(spectators)).unwrap();
+        let gone = remove_lasers_of(dead_id, lasers);
+        if !gone.is_empty() {
+            socket.send_all(ServerPacket::DeleteLasers(gone), connections.keys().chain(spectators)).unwrap();
+        }
     }
 }
 
-#[inline]
-fn fit_in<T>(elem: T, tree_map: &mut BTreeMap<Idx, T>) -> Idx {
-    let idx = (0..).filter(|i| !tree_map.contains_key(i)).next().unwrap();
-    let old = tree_map.insert(idx, elem);
-    debug_assert!(old.is_none());
-    idx
+/// Adds a new ship to the room, held back until the next match if one is going on
+///
+/// Returns its index, the ship and whether it was held back.
+fn join(players: &mut BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, mode: &dyn GameMode, lifecycle: &Lifecycle, recorder: &Mutex<Option<Recorder>>) -> (Idx, Player, bool) {
+    let mut player = Player {
+        lives: mode.lives(),
+        team: mode.assign_team(players),
+        .. Player::default()
+    };
+    // Joining mid-match means watching until the next one, if there is going to be one
+    let queued = lifecycle.queues_joins() && mode.can_end();
+    if queued {
+        player.respawn = Some(f32::INFINITY);
+    } else {
+        player.spawn(spawn_point(&mut thread_rng(), players, planets, player.team, mode.teams()), thread_rng().gen_range(-PI, PI));
+    }
+    let idx = fit_in(player, players);
+    record(recorder, Input::Join(idx, player));
+    (idx, player, queued)
+}
+
+/// Makes ship `i` follow `command`, the same way for players and fill-in bots
+///
+/// Returns what everyone watching needs to be told, unless the ship is dead.
+fn follow(players: &mut BTreeMap<Idx, Player>, lasers: &Mutex<BTreeMap<Idx, Laser>>, recorder: &Mutex<Option<Recorder>>, i: Idx, command: Command) -> Option<ServerPacket> {
+    let player = players.get_mut(&i).unwrap();
+    if !player.is_alive() {
+        return None
+    }
+    let fired = player.command(i, command);
+    record(recorder, Input::Command(i, command));
+    Some(match fired {
+        Some(new_laser) => {
+            let idx = fit_in(new_laser, &mut lasers.lock().unwrap());
+            ServerPacket::UpdateLaser(idx, new_laser.obj)
+        }
+        None => ServerPacket::UpdatePlayer(i, player.obj),
+    })
+}
+
+fn announce_mode(mode: &dyn GameMode) -> ServerPacket {
+    ServerPacket::GameMode {
+        mode: mode.kind(),
+        teams: mode.teams(),
+        time_limit: mode.time_limit(),
+    }
 }
 
 impl Server {
-    pub fn new() -> Self {
-        let planets = vec![
-            Planet::new(0., 0., 10., 2.),
-            Planet::new(50., 0., -10., 2.),
-            Planet::new(0., 0., 10., -2.),
-            Planet::new(0., 0., -10., -2.),
-            Planet::new(0., 400., 50., -20.),
-        ];
+    pub fn new(id: RoomId, config: Config, server_socket: Arc<ServerSocket>) -> Self {
+        let mut planets = starting_planets();
+        let mut mode = mode::from_config(&config);
+        mode.setup(&BTreeMap::new(), &mut planets);
+        let sim = Simulation::new(Rules {
+            teams: mode.teams(),
+            friendly_fire: config.friendly_fire,
+            respawn_time: config.respawn_time,
+        }, thread_rng().gen());
 
         Server {
-            planets: Arc::new(Mutex::new((0..).zip(planets.into_iter()).collect())),
-            deads: Vec::new(),
+            id,
+            config,
+            mode: Arc::new(Mutex::new(mode)),
+            lifecycle: Arc::new(Mutex::new(Lifecycle::new())),
+            planets: Arc::new(Mutex::new(planets)),
             lasers: Arc::default(),
+            sim,
+            recorder: Arc::default(),
+            matches: 0,
+            match_time: 0.,
+            fill_ins: BTreeMap::new(),
+            autopilots: BTreeMap::new(),
             players: Arc::default(),
             connections: Arc::default(),
-            server_socket: Arc::new(ServerSocket::new((Ipv4Addr::new(0, 0, 0, 0), 7351))),
+            spectators: Arc::default(),
+            autopilot: Arc::default(),
+            server_socket,
         }
     }
     pub fn update(&mut self, delta: f32) {
-        let mut dead_lasers = Vec::new();
-        let mut dead_planets = Vec::new();
-        let player_addrs: Vec<_> = self.connections.lock().unwrap().keys().cloned().collect();
+        let audience = self.audience(&self.connections.lock().unwrap());
+
+        let mut players = self.players.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
+        let mut planets = self.planets.lock().unwrap();
+        let mut lasers = self.lasers.lock().unwrap();
 
-        for (&i, planet) in self.planets.lock().unwrap().iter_mut() {
-            for (&j, laser) in self.lasers.lock().unwrap().iter() {
-                if planet.obj.pos().distance_to(laser.pos()) < 32. {
-                    dead_lasers.push(j);
-                    planet.health = planet.health.saturating_sub(1);
+        let mut events = Vec::new();
+        self.sim.step(&mut players, &mut planets, &mut lasers, delta, &mut events);
+
+        if let Some(ref mut recorder) = *self.recorder.lock().unwrap() {
+            let snapshot = || Snapshot {
+                players: players.clone(),
+                planets: planets.clone(),
+                lasers: lasers.clone(),
+            };
+            if let Err(e) = recorder.frame(delta, snapshot) {
+                println!("Couldn't record frame: {}", e);
+            }
+        }
+
+        let addrs: HashMap<_, _> = audience.iter().filter_map(|addr| connections.get(addr).map(|&i| (i, *addr))).collect();
+        for event in events {
+            match event {
+                Event::Respawned(i) => {
+                    self.server_socket.send_all(ServerPacket::Respawned(i, players[&i].obj), audience.iter()).unwrap();
+                    if let Some(addr) = addrs.get(&i) {
+                        self.server_socket.send(ServerPacket::UpdateStatus(players[&i].status), addr).unwrap();
+                    }
+                }
+                // It might have hit something later in the step
+                Event::LaserMoved(l) => if let Some(laser) = lasers.get(&l) {
+                    self.server_socket.send_all(ServerPacket::UpdateLaser(l, laser.obj), audience.iter()).unwrap();
+                },
+                Event::PlanetMoved(i) => {
+                    self.server_socket.send_all(ServerPacket::UpdatePlanet(i, planets[&i].obj), audience.iter()).unwrap();
+                }
+                Event::Damage{target, by, damage} => {
+                    self.server_socket.send_all(ServerPacket::Damage{target, by, damage}, audience.iter()).unwrap();
+                }
+                Event::StatusChanged(i) => if let Some(addr) = addrs.get(&i) {
+                    self.server_socket.send(ServerPacket::UpdateStatus(players[&i].status), addr).unwrap();
+                },
+                Event::Died{player, killer, pos, respawn_in} => {
+                    println!("Player {} died!", player);
+                    self.server_socket.send_all(ServerPacket::Died {
+                        player,
+                        killer,
+                        pos,
+                        respawn_in,
+                    }, audience.iter()).unwrap();
+                }
+                Event::PlanetsDestroyed(dead_planets) => {
+                    self.server_socket.send_all(ServerPacket::DeletePlanets(dead_planets), audience.iter()).unwrap();
+                }
+                Event::LasersGone(dead_lasers) => {
+                    self.server_socket.send_all(ServerPacket::DeleteLasers(dead_lasers), audience.iter()).unwrap();
                 }
             }
+        }
+    }
+    /// Everyone watching the room, players and spectators
+    ///
+    /// Nobody with a virtual clock, it ticks far faster than clients could keep up with.
+    fn audience(&self, connections: &HashMap<SocketAddr, Idx>) -> Vec<SocketAddr> {
+        if self.config.virtual_clock.is_some() {
+            return Vec::new()
+        }
+        connections.keys().chain(self.spectators.lock().unwrap().iter()).cloned().collect()
+    }
+    fn send_scoreboard(&self) {
+        let players = self.players.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
 
-            if planet.health == 0 {
-                dead_planets.push(i)
-            } else {
-                planet.obj.update(delta);
+        let mode = self.mode.lock().unwrap();
 
-                if planet.obj.stay_in_bounds() {
-                    self.server_socket.send_all(ServerPacket::UpdatePlanet(i, planet.obj), player_addrs.iter()).unwrap();
-                }
+        let mut stats: Vec<_> = players.iter()
+            .map(|(&i, p)| (i, mode.score(&p.stats), p.stats))
+            .collect();
+        while !stats.is_empty() {
+            let start = stats.len().saturating_sub(SCOREBOARD_CHUNK);
+            let to_send = stats.drain(start..).collect();
+            self.server_socket.send_all(ServerPacket::Scoreboard(to_send), self.audience(&connections).iter()).unwrap();
+        }
+    }
+    /// Decides whether the match is over
+    fn outcome(&self) -> Option<Outcome> {
+        let players = self.players.lock().unwrap();
+        let planets = self.planets.lock().unwrap();
+        let mode = self.mode.lock().unwrap();
+        mode.outcome(&players, &planets, self.match_time)
+    }
+    /// Starts a fresh replay if replays are being recorded
+    fn start_recording(&mut self) {
+        let dir = match self.config.record {
+            Some(ref dir) => dir,
+            None => return,
+        };
+        self.matches += 1;
+        let seed = thread_rng().gen();
+        self.sim.reseed(seed);
+
+        let players = self.players.lock().unwrap();
+        let planets = self.planets.lock().unwrap();
+        let lasers = self.lasers.lock().unwrap();
+        let mode = self.mode.lock().unwrap();
+        let header = Header {
+            version: REPLAY_VERSION,
+            room: self.config.name.clone(),
+            mode: mode.kind(),
+            rules: self.sim.rules,
+            seed,
+            initial: Snapshot {
+                players: players.clone(),
+                planets: planets.clone(),
+                lasers: lasers.clone(),
+            },
+        };
+        let path = format!("{}/{}-{}.vxr", dir, self.config.name, self.matches);
+        *self.recorder.lock().unwrap() = match Recorder::create(&path, &header, KEYFRAME_INTERVAL) {
+            Ok(recorder) => {
+                println!("Recording to {}", path);
+                Some(recorder)
+            }
+            Err(e) => {
+                println!("Couldn't record to {}: {}", path, e);
+                None
             }
+        };
+    }
+    /// Records the result of the match
+    fn finish_match(&mut self, outcome: Outcome) {
+        if let Some(mut recorder) = self.recorder.lock().unwrap().take() {
+            if let Err(e) = recorder.flush() {
+                println!("Couldn't finish recording: {}", e);
+            }
+        }
+        let players = self.players.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
+        let mode = self.mode.lock().unwrap();
+
+        println!("Match over: {:?}", outcome);
+        self.server_socket.send_all(ServerPacket::MatchOver(outcome), self.audience(&connections).iter()).unwrap();
+
+        let addrs: HashMap<_, _> = connections.iter().map(|(&addr, &i)| (i, addr)).collect();
+        let result = MatchResult::new(&self.config.name, mode.kind(), outcome, self.match_time,
+            players.iter().map(|(&i, p)| (i, addrs.get(&i).cloned(), p.team, mode.score(&p.stats), p.stats)));
+        if let Err(e) = result.emit(self.config.results.as_deref()) {
+            println!("Couldn't write match result: {}", e);
         }
+    }
+    /// Puts the world back the way the game mode wants it and respawns every ship
+    fn reset_world(&mut self) {
         {
+            let mut players = self.players.lock().unwrap();
+            let connections = self.connections.lock().unwrap();
             let mut planets = self.planets.lock().unwrap();
-            for i in dead_planets.iter() {
-                planets.remove(i);
+            let mut lasers = self.lasers.lock().unwrap();
+            let mut mode = self.mode.lock().unwrap();
+            let addrs = self.audience(&connections);
+
+            let old_planets: Vec<_> = planets.keys().cloned().collect();
+            mode.setup(&players, &mut planets);
+            let gone = old_planets.into_iter().filter(|i| !planets.contains_key(i)).collect();
+            self.server_socket.send_all(ServerPacket::DeletePlanets(gone), addrs.iter()).unwrap();
+            for (&i, planet) in planets.iter() {
+                self.server_socket.send_all(ServerPacket::UpdatePlanet(i, planet.obj), addrs.iter()).unwrap();
             }
-        }
+            let lasers = ::std::mem::take(&mut *lasers);
+            self.server_socket.send_all(ServerPacket::DeleteLasers(lasers.keys().cloned().collect()), addrs.iter()).unwrap();
 
-        if !dead_planets.is_empty() {
-            self.server_socket.send_all(ServerPacket::DeletePlanets(dead_planets), player_addrs.iter()).unwrap();
+            // Nobody is in the way yet, so ships don't avoid each other's old positions
+            for player in players.values_mut() {
+                player.respawn = Some(0.);
+            }
+            let owners: HashMap<_, _> = connections.iter().map(|(&addr, &i)| (i, addr)).collect();
+            let ids: Vec<_> = players.keys().cloned().collect();
+            for i in ids {
+                let pos = spawn_point(&mut thread_rng(), &players, &planets, players[&i].team, mode.teams());
+                let player = players.get_mut(&i).unwrap();
+                player.stats = Default::default();
+                player.lives = mode.lives();
+                player.spawn(pos, thread_rng().gen_range(-PI, PI));
+                self.server_socket.send_all(ServerPacket::Respawned(i, player.obj), addrs.iter()).unwrap();
+                if let Some(addr) = owners.get(&i) {
+                    self.server_socket.send(ServerPacket::UpdateStatus(player.status), addr).unwrap();
+                }
+            }
+
+            self.server_socket.send_all(announce_mode(&**mode), addrs.iter()).unwrap();
         }
+        self.match_time = 0.;
+        self.send_scoreboard();
+    }
+    fn enter_phase(&mut self, phase: MatchPhase, time: Option<f32>) {
+        let connections = self.connections.lock().unwrap();
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        lifecycle.enter(phase, time);
+        println!("Entering {:?}", phase);
+        self.server_socket.send_all(lifecycle.packet(), self.audience(&connections).iter()).unwrap();
+    }
+    /// Moves the match along to its next phase when it's time
+    fn advance_lifecycle(&mut self, delta: f32) {
+        let enough_players = self.players.lock().unwrap().len() >= self.config.min_players;
+        let (phase, expired) = {
+            let mut lifecycle = self.lifecycle.lock().unwrap();
+            let expired = lifecycle.tick(delta);
+            (lifecycle.phase, expired)
+        };
 
-        for (addr, i) in self.connections.lock().unwrap().iter_mut() {
-            let mut players = self.players.lock().unwrap();
-            let player = players.get_mut(i).unwrap();
-            for (&l, laser) in self.lasers.lock().unwrap().iter() {
-                if player.obj.pos().distance_to(laser.pos()) < 32. {
-                    player.health = player.health.saturating_sub(1);
-                    self.server_socket.send(ServerPacket::UpdateHealth(player.health), addr).unwrap();
-                    dead_lasers.push(l);
+        match phase {
+            MatchPhase::Waiting if enough_players => {
+                let warmup = self.config.warmup_time;
+                self.enter_phase(MatchPhase::Warmup, Some(warmup));
+            }
+            MatchPhase::Warmup if !enough_players => self.enter_phase(MatchPhase::Waiting, None),
+            MatchPhase::Warmup if expired => {
+                self.reset_world();
+                let countdown = self.config.countdown_time;
+                self.enter_phase(MatchPhase::Countdown, Some(countdown));
+            }
+            MatchPhase::Countdown if expired => {
+                self.match_time = 0.;
+                self.start_recording();
+                self.enter_phase(MatchPhase::Running, None);
+            }
+            MatchPhase::Running => {
+                self.match_time += delta;
+                // A match everyone else has left is called off
+                let outcome = self.outcome().or(if enough_players { None } else { Some(Outcome::Draw) });
+                if let Some(outcome) = outcome {
+                    self.finish_match(outcome);
+                    let results = self.config.results_time;
+                    self.enter_phase(MatchPhase::Results(outcome), Some(results));
                 }
             }
-            if player.health == 0 {
-                println!("{} died!", addr);
-                self.deads.push(addr.clone());
+            MatchPhase::Results(_) if expired => {
+                self.reset_world();
+                self.enter_phase(MatchPhase::Waiting, None);
             }
-
-            player.obj.update(delta);
-            player.obj.stay_in_bounds();
+            _ => (),
         }
+    }
+    /// Adds or removes fill-in bots to keep `fill_to` ships in the room while anyone is playing
+    ///
+    /// With a virtual clock nobody can play along, so the bots play among themselves.
+    /// Bots that crashed are replaced.
+    fn balance_fill_ins(&mut self) {
+        let wanted = {
+            let connections = self.connections.lock().unwrap();
+            if connections.is_empty() && self.config.virtual_clock.is_none() {
+                0
+            } else {
+                self.config.fill_to.saturating_sub(connections.len())
+            }
+        };
+        let mut leaving: Vec<Idx> = self.fill_ins.iter().filter(|&(_, pilot)| pilot.has_crashed()).map(|(&i, _)| i).collect();
 
-        dead_lasers.dedup();
-
-        let mut lasers = self.lasers.lock().unwrap();
-
-        if !dead_lasers.is_empty() {
-            for l in dead_lasers.iter() {
-                lasers.remove(l);
+        // Starting a ship program can take a while, so it's done before anything gets locked
+        let mut controllers = Vec::new();
+        while controllers.len() + self.fill_ins.len() < wanted + leaving.len() {
+            match load(&self.config.fill_bot) {
+                Ok(controller) => controllers.push(controller),
+                Err(e) => {
+                    println!("Couldn't start fill-in bot {:?}, not filling in any more: {}", self.config.fill_bot, e);
+                    self.config.fill_to = 0;
+                    break
+                }
             }
-            self.server_socket.send_all(ServerPacket::DeleteLasers(dead_lasers), player_addrs.iter()).unwrap();
         }
 
-        for (&i, laser) in lasers.iter_mut() {
-            laser.update(delta);
+        let mut players = self.players.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
+        let audience = self.audience(&connections);
 
-            if laser.stay_in_bounds() {
-                self.server_socket.send_all(ServerPacket::UpdateLaser(i, *laser), player_addrs.iter()).unwrap();
+        while self.fill_ins.len() > wanted + leaving.len() {
+            // Leaving from the biggest team keeps the teams even
+            let team_size = |team| players.iter().filter(|&(i, p)| p.team == team && !leaving.contains(i)).count();
+            let i = *self.fill_ins.keys().filter(|i| !leaving.contains(i)).max_by_key(|i| team_size(players[i].team)).unwrap();
+            leaving.push(i);
+        }
+        for i in leaving {
+            let crashed = self.fill_ins.remove(&i).unwrap().has_crashed();
+            players.remove(&i);
+            record(&self.recorder, Input::Leave(i));
+            self.server_socket.send_all(ServerPacket::DeletePlayer(i), audience.iter()).unwrap();
+            let gone = remove_lasers_of(i, &mut self.lasers.lock().unwrap());
+            if !gone.is_empty() {
+                self.server_socket.send_all(ServerPacket::DeleteLasers(gone), audience.iter()).unwrap();
+            }
+            if crashed {
+                println!("Fill-in bot {} crashed and left {}", i, self.config.name);
+            } else {
+                println!("Fill-in bot {} left {}", i, self.config.name);
             }
         }
-        if let Some(dead) = self.deads.pop() {
-            let mut players = self.players.lock().unwrap();
-            let mut connections = self.connections.lock().unwrap();
-            remove_player(&self.server_socket, &mut connections, &mut players, dead);
+        if !controllers.is_empty() {
+            let planets = self.planets.lock().unwrap();
+            let mode = self.mode.lock().unwrap();
+            let lifecycle = self.lifecycle.lock().unwrap();
+            for controller in controllers {
+                let (i, player, queued) = join(&mut players, &planets, &**mode, &lifecycle, &self.recorder);
+                self.fill_ins.insert(i, Pilot::spawn(controller));
+                self.server_socket.send_all(ServerPacket::Team(i, player.team), audience.iter()).unwrap();
+                if !queued {
+                    self.server_socket.send_all(ServerPacket::UpdatePlayer(i, player.obj), audience.iter()).unwrap();
+                }
+                println!("Fill-in bot {} joined {}", i, self.config.name);
+            }
         }
     }
-    pub fn run(mut self) {
-        let listener_server_socket = self.server_socket.clone();
-        let listener_players = self.players.clone();
-        let listener_connections = self.connections.clone();
-        let listener_lasers = self.lasers.clone();
-        let listener_planets = self.planets.clone();
-
-        let _listener = thread::spawn(move || {
-            loop {
-                let (remote, packet) = listener_server_socket.recv().unwrap();
-                let mut players = listener_players.lock().unwrap();
-                let mut connections = listener_connections.lock().unwrap();
-                let mut to_send = None;
-                match packet {
-                    ClientPacket::Connect => {
-                        listener_server_socket.send(ServerPacket::PlayersAndPlanets {
-                            planets: listener_planets.lock().unwrap().iter().map(|(&i, p)| (i, p.obj)).collect(),
-                            players: players.iter().map(|(&i, p)| (i, p.obj)).collect()
-                        }, &remote).unwrap();
-                        listener_server_socket.send(ServerPacket::UpdateHealth(5), &remote).unwrap();
-                        let mut lasers: Vec<_> = listener_lasers.lock().unwrap().iter()
-                            .map(|(&i, &l)| (i, l)).collect();
-                        while !lasers.is_empty() {
-                            let start = lasers.len().saturating_sub(46);
-                            let to_send = lasers.drain(start..).collect();
-                            listener_server_socket.send(ServerPacket::Lasers(to_send),
-                                                        &remote).unwrap();
-                        }
-                        println!("{} connected!", remote);
-
-                        let idx = fit_in(Player::default(), &mut players);
-                        connections.insert(remote, idx);
-                        to_send = Some(ServerPacket::UpdatePlayer(idx, RotatableObject::default()));
-                    }
-                    ClientPacket::PlayerImpulse(a) => {
-                        if let Some(i) = connections.get(&remote) {
-                            let player = players.get_mut(i).unwrap();
-                            player.obj.acceleration = a * Vector2::unit_vector(player.obj.rotation);
-                            to_send = Some(ServerPacket::UpdatePlayer(*i, player.obj));
-                        }
-                    }
-                    ClientPacket::PlayerRotate(r) => {
-                        if let Some(i) = connections.get(&remote) {
-                            let player = players.get_mut(i).unwrap();
-                            player.obj.rotation += r;
-                            to_send = Some(ServerPacket::UpdatePlayer(*i, player.obj));
-                        }
-                    }
-                    ClientPacket::Shoot => {
-                        if let Some(i) = connections.get(&remote) {
-                            let player = players[i].obj;
-                            let dir = Vector2::unit_vector(player.rotation);
-
-                            let new_laser = RotatableObject::new(player.pos() + 42. * dir,
-                                player.vel() + 400. * dir, player.rotation);
-
-                            let mut lasers = listener_lasers.lock().unwrap();
-                            let idx = fit_in(new_laser, &mut lasers);
-                            to_send = Some(ServerPacket::UpdateLaser(idx, new_laser));
-                        }
-                    }
-                    ClientPacket::Disconnect => {
-                        remove_player(&listener_server_socket, &mut connections, &mut players, remote);
-                    }
-                }
+    /// Hands the ships of players who asked for it to the room's fill-in bot, and back when they ask
+    ///
+    /// Bots that crashed are replaced, ones that can't be started give the ship back.
+    fn balance_autopilots(&mut self) {
+        let wanted: HashMap<Idx, SocketAddr> = {
+            let connections = self.connections.lock().unwrap();
+            let autopilot = self.autopilot.lock().unwrap();
+            autopilot.iter().filter_map(|addr| connections.get(addr).map(|&i| (i, *addr))).collect()
+        };
+        self.autopilots.retain(|i, pilot| wanted.contains_key(i) && !pilot.has_crashed());
 
-                if let Some(packet) = to_send {
-                    listener_server_socket.send_all(packet, connections.keys()).unwrap();
+        for (i, addr) in wanted {
+            if self.autopilots.contains_key(&i) {
+                continue
+            }
+            match load(&self.config.fill_bot) {
+                Ok(controller) => {
+                    self.autopilots.insert(i, Pilot::spawn(controller));
+                }
+                Err(e) => {
+                    println!("Couldn't start {:?} to fly player {}: {}", self.config.fill_bot, i, e);
+                    self.autopilot.lock().unwrap().remove(&addr);
                 }
             }
-        });
+        }
+    }
+    /// Lets every fill-in bot and autopilot fly its ship, going by what it decided on the tick before
+    ///
+    /// Bots think on their own threads while the room ticks, a slow one holds it up by a tick's
+    /// pause at most. With a virtual clock every answer is waited for, so runs don't depend on
+    /// how the threads happen to be scheduled, up until a bot gets stuck and is replaced.
+    /// What autopilots show about their thinking goes to the player whose ship they fly.
+    fn fly_bots(&mut self, delta: f32) {
+        let mut players = self.players.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
+        let audience = self.audience(&connections);
+        let owners: HashMap<_, _> = audience.iter().filter_map(|addr| connections.get(addr).map(|&i| (i, *addr))).collect();
+        let deadline = match self.config.virtual_clock {
+            Some(_) => None,
+            None => Some(Instant::now() + TICK_PAUSE),
+        };
 
-        let mut last_time = Instant::now();
-        let mut aggregate_time = Duration::new(0, 0);
-        let spawn_time = Duration::from_secs(10);
+        for (&i, pilot) in self.fill_ins.iter_mut().chain(self.autopilots.iter_mut()) {
+            if !players[&i].is_alive() {
+                continue
+            }
+            let controls = pilot.answer(deadline);
+            for c in controls.commands(&players[&i], delta) {
+                if let Some(packet) = follow(&mut players, &self.lasers, &self.recorder, i, c) {
+                    self.server_socket.send_all(packet, audience.iter()).unwrap();
+                }
+            }
+        }
+        for (i, pilot) in self.autopilots.iter_mut() {
+            if let (Some(addr), Some(debug)) = (owners.get(i), pilot.debug()) {
+                for packet in debug_packets(debug) {
+                    self.server_socket.send(packet, addr).unwrap();
+                }
+            }
+        }
+        let planets = self.planets.lock().unwrap();
+        let lasers = self.lasers.lock().unwrap();
+        let radar = Radar::new(&players, &planets, &lasers);
+        for (&i, pilot) in self.fill_ins.iter_mut().chain(self.autopilots.iter_mut()) {
+            if players[&i].is_alive() {
+                pilot.ask(radar.sense(&players, &planets, &lasers, i, self.match_time));
+            }
+        }
+    }
+    /// Whether the room is on a virtual clock with nobody in it waiting for anyone to join
+    fn is_idle(&self) -> bool {
+        self.config.virtual_clock.is_some() && self.players.lock().unwrap().is_empty()
+            && self.lifecycle.lock().unwrap().phase == MatchPhase::Waiting
+    }
+    pub fn handle(&self) -> Handle {
+        Handle {
+            id: self.id,
+            name: self.config.name.clone(),
+            max_players: self.config.max_players,
+            max_spectators: self.config.max_spectators,
+            mode: self.mode.clone(),
+            lifecycle: self.lifecycle.clone(),
+            planets: self.planets.clone(),
+            server_socket: self.server_socket.clone(),
+            connections: self.connections.clone(),
+            spectators: self.spectators.clone(),
+            autopilot: self.autopilot.clone(),
+            players: self.players.clone(),
+            lasers: self.lasers.clone(),
+            recorder: self.recorder.clone(),
+        }
+    }
+    /// Runs the room's tick loop, packets from its players are handed to it through its `Handle`
+    pub fn run(mut self) {
+        let mut clock = match self.config.virtual_clock {
+            Some(tick) => Clock::virtual_time(tick),
+            None => Clock::real(TICK_PAUSE),
+        };
+        let mut aggregate_time = 0.;
+        let spawn_time = 10.;
+        let mut scoreboard_time = 0.;
+        let scoreboard_interval = 1.;
 
         loop {
-            let now = Instant::now();
-            let dur = now-last_time;
-            last_time = now;
+            self.balance_fill_ins();
+            self.balance_autopilots();
+            // A virtual clock would spin through an empty room as fast as it can
+            if self.is_idle() {
+                thread::sleep(TICK_PAUSE);
+                continue
+            }
+            let delta = clock.tick();
+
+            let simulating = self.lifecycle.lock().unwrap().allows_input();
 
-            if self.planets.lock().unwrap().len() < 5 {
-                aggregate_time += dur;
+            if simulating && self.planets.lock().unwrap().len() < 5 {
+                aggregate_time += delta;
                 if aggregate_time >= spawn_time {
                     aggregate_time -= spawn_time;
                     let new_planet: Planet = ::rand::random();
                     let mut planets = self.planets.lock().unwrap();
                     let idx = fit_in(new_planet, &mut planets);
+                    record(&self.recorder, Input::AddPlanet(idx, new_planet));
                     self.server_socket.send_all(
                         ServerPacket::UpdatePlanet(idx, new_planet.obj),
-                        self.connections.lock().unwrap().keys()
+                        self.audience(&self.connections.lock().unwrap()).iter()
                     ).unwrap();
                 }
             }
 
-            self.update(dur.as_secs() as f32 + 1e-9 * dur.subsec_nanos() as f32);
-            thread::sleep(Duration::from_millis(18));
+            if simulating {
+                self.fly_bots(delta);
+                self.update(delta);
+            }
+            self.advance_lifecycle(delta);
+
+            scoreboard_time += delta;
+            if scoreboard_time >= scoreboard_interval {
+                scoreboard_time -= scoreboard_interval;
+                self.send_scoreboard();
+            }
+        }
+    }
+}
+
+/// What the listener needs to pass packets on to a room
+#[derive(Clone)]
+pub struct Handle {
+    pub id: RoomId,
+    name: String,
+    max_players: usize,
+    max_spectators: usize,
+    mode: Arc<Mutex<Box<dyn GameMode>>>,
+    lifecycle: Arc<Mutex<Lifecycle>>,
+    planets: Arc<Mutex<BTreeMap<Idx, Planet>>>,
+    server_socket: Arc<ServerSocket>,
+    connections: Arc<Mutex<HashMap<SocketAddr, Idx>>>,
+    spectators: Arc<Mutex<HashSet<SocketAddr>>>,
+    autopilot: Arc<Mutex<HashSet<SocketAddr>>>,
+    players: Arc<Mutex<BTreeMap<Idx, Player>>>,
+    lasers: Arc<Mutex<BTreeMap<Idx, Laser>>>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+}
+
+impl Handle {
+    pub fn is_full(&self) -> bool {
+        self.connections.lock().unwrap().len() >= self.max_players
+    }
+    pub fn has_spectator_room(&self) -> bool {
+        self.spectators.lock().unwrap().len() < self.max_spectators
+    }
+    /// Ships in the room, including ones nobody is flying
+    #[cfg(test)]
+    pub fn ships(&self) -> usize {
+        self.players.lock().unwrap().len()
+    }
+    pub fn info(&self) -> RoomInfo {
+        let players = self.connections.lock().unwrap().len();
+        let spectators = self.spectators.lock().unwrap().len();
+        let mode = self.mode.lock().unwrap();
+        let lifecycle = self.lifecycle.lock().unwrap();
+        RoomInfo {
+            id: self.id,
+            name: self.name.clone(),
+            mode: mode.kind(),
+            players: players as u16,
+            max_players: self.max_players as u16,
+            spectators: spectators as u16,
+            phase: lifecycle.phase,
+        }
+    }
+    /// Sends everything in the room to someone who just arrived
+    fn send_world(&self, players: &BTreeMap<Idx, Player>, planets: &BTreeMap<Idx, Planet>, remote: &SocketAddr) {
+        self.server_socket.send(ServerPacket::PlayersAndPlanets {
+            planets: planets.iter().map(|(&i, p)| (i, p.obj)).collect(),
+            players: players.iter().filter(|&(_, p)| p.is_alive()).map(|(&i, p)| (i, p.obj)).collect()
+        }, remote).unwrap();
+        let mut lasers: Vec<_> = self.lasers.lock().unwrap().iter()
+            .map(|(&i, l)| (i, l.obj)).collect();
+        while !lasers.is_empty() {
+            let start = lasers.len().saturating_sub(46);
+            let to_send = lasers.drain(start..).collect();
+            self.server_socket.send(ServerPacket::Lasers(to_send), remote).unwrap();
+        }
+        for (&i, p) in players.iter() {
+            self.server_socket.send(ServerPacket::Team(i, p.team), remote).unwrap();
+        }
+        self.server_socket.send(announce_mode(&**self.mode.lock().unwrap()), remote).unwrap();
+        self.server_socket.send(self.lifecycle.lock().unwrap().packet(), remote).unwrap();
+    }
+    pub fn handle(&self, remote: SocketAddr, packet: ClientPacket) {
+        let mut players = self.players.lock().unwrap();
+        let mut connections = self.connections.lock().unwrap();
+        let mut spectators = self.spectators.lock().unwrap();
+        let mut to_send = None;
+        let allows_input = self.lifecycle.lock().unwrap().allows_input();
+        match packet {
+            ClientPacket::Connect => {
+                let planets = self.planets.lock().unwrap();
+                self.send_world(&players, &planets, &remote);
+                println!("{} connected to {}!", remote, self.name);
+
+                let mode = self.mode.lock().unwrap();
+                let lifecycle = self.lifecycle.lock().unwrap();
+
+                let (idx, player, queued) = join(&mut players, &planets, &**mode, &lifecycle, &self.recorder);
+                self.server_socket.send(ServerPacket::UpdateStatus(player.status), &remote).unwrap();
+                self.server_socket.send(ServerPacket::Joined{id: idx, team: player.team}, &remote).unwrap();
+                self.server_socket.send_all(ServerPacket::Team(idx, player.team), connections.keys().chain(spectators.iter())).unwrap();
+                connections.insert(remote, idx);
+                if !queued {
+                    to_send = Some(ServerPacket::UpdatePlayer(idx, player.obj));
+                }
+            }
+            ClientPacket::PlayerImpulse(_) | ClientPacket::PlayerRotate(_) | ClientPacket::Shoot{..} => {
+                let command = match packet {
+                    ClientPacket::PlayerImpulse(a) => Command::Thrust(a),
+                    ClientPacket::PlayerRotate(r) => Command::Rotate(r),
+                    ClientPacket::Shoot{weapon} => Command::Shoot(weapon),
+                    _ => unreachable!(),
+                };
+                // The autopilot has the controls
+                let flying = allows_input && !self.autopilot.lock().unwrap().contains(&remote);
+                if let (Some(&i), true) = (connections.get(&remote), flying) {
+                    to_send = follow(&mut players, &self.lasers, &self.recorder, i, command);
+                }
+            }
+            ClientPacket::Autopilot(on) => if connections.contains_key(&remote) {
+                let mut autopilot = self.autopilot.lock().unwrap();
+                if on {
+                    autopilot.insert(remote);
+                } else {
+                    autopilot.remove(&remote);
+                }
+            },
+            ClientPacket::Spectate(_) => {
+                let planets = self.planets.lock().unwrap();
+                self.send_world(&players, &planets, &remote);
+                spectators.insert(remote);
+                println!("{} is spectating {}", remote, self.name);
+            }
+            // The lobby deals with these
+            ClientPacket::ListRooms | ClientPacket::JoinRoom(_) => (),
+            ClientPacket::Disconnect => {
+                if spectators.remove(&remote) {
+                    self.server_socket.send(ServerPacket::DisconnectAck, &remote).unwrap();
+                } else {
+                    if let Some(&i) = connections.get(&remote) {
+                        record(&self.recorder, Input::Leave(i));
+                    }
+                    self.autopilot.lock().unwrap().remove(&remote);
+                    let mut lasers = self.lasers.lock().unwrap();
+                    remove_player(&self.server_socket, &mut connections, &spectators, &mut players, &mut lasers, remote);
+                }
+            }
+        }
+
+        if let Some(packet) = to_send {
+            self.server_socket.send_all(packet, connections.keys().chain(spectators.iter())).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    /// Runs the room's lifecycle on until its match is going
+    fn start_match(server: &mut Server) {
+        for _ in 0..3 {
+            server.advance_lifecycle(100.);
+        }
+        assert_eq!(server.lifecycle.lock().unwrap().phase, MatchPhase::Running);
+    }
+
+    #[test]
+    fn leaving_mid_match_calls_it_off() {
+        let mut server = Server::new(0, Config::default(), Arc::new(ServerSocket::new("127.0.0.1:0")));
+        let handle = server.handle();
+        let clients: Vec<_> = (0..2).map(|_| UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+        let addrs: Vec<_> = clients.iter().map(|c| c.local_addr().unwrap()).collect();
+        for &addr in &addrs {
+            handle.handle(addr, ClientPacket::Connect);
+        }
+        start_match(&mut server);
+
+        handle.handle(addrs[0], ClientPacket::Disconnect);
+        server.advance_lifecycle(0.1);
+        assert_eq!(server.lifecycle.lock().unwrap().phase, MatchPhase::Results(Outcome::Draw));
+        server.advance_lifecycle(100.);
+        assert_eq!(server.lifecycle.lock().unwrap().phase, MatchPhase::Waiting);
+    }
+
+    #[test]
+    fn virtual_clock_rooms_fill_in_without_anyone_there() {
+        let config = Config {
+            virtual_clock: Some(1. / 60.),
+            fill_to: 2,
+            fill_bot: "idle".to_owned(),
+            .. Config::default()
+        };
+        let mut server = Server::new(0, config, Arc::new(ServerSocket::new("127.0.0.1:0")));
+        assert!(server.is_idle());
+        server.balance_fill_ins();
+        assert_eq!(server.fill_ins.len(), 2);
+        assert!(!server.is_idle());
+
+        let mut server = Server::new(0, Config { fill_to: 2, .. Config::default() }, Arc::new(ServerSocket::new("127.0.0.1:0")));
+        assert!(!server.is_idle());
+        server.balance_fill_ins();
+        assert!(server.fill_ins.is_empty());
+    }
+
+    #[test]
+    fn autopilots_take_over_and_give_back_ships() {
+        let config = Config {
+            fill_bot: "idle".to_owned(),
+            .. Config::default()
+        };
+        let mut server = Server::new(0, config, Arc::new(ServerSocket::new("127.0.0.1:0")));
+        let handle = server.handle();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = client.local_addr().unwrap();
+        handle.handle(addr, ClientPacket::Connect);
+        let i = server.connections.lock().unwrap()[&addr];
+
+        handle.handle(addr, ClientPacket::Autopilot(true));
+        server.balance_autopilots();
+        assert!(server.autopilots.contains_key(&i));
+        // Only the autopilot flies the ship
+        handle.handle(addr, ClientPacket::PlayerImpulse(100.));
+        assert_eq!(server.players.lock().unwrap()[&i].thrust, 0.);
+        server.fly_bots(0.01);
+        server.fly_bots(0.01);
+
+        handle.handle(addr, ClientPacket::Autopilot(false));
+        server.balance_autopilots();
+        assert!(server.autopilots.is_empty());
+        handle.handle(addr, ClientPacket::PlayerImpulse(100.));
+        assert_eq!(server.players.lock().unwrap()[&i].thrust, 100.);
+
+        handle.handle(addr, ClientPacket::Autopilot(true));
+        handle.handle(addr, ClientPacket::Disconnect);
+        server.balance_autopilots();
+        assert!(server.autopilots.is_empty());
+    }
+
+    #[test]
+    fn joins_only_wait_for_matches_that_end() {
+        let endless = Config::default();
+        let timed = Config {
+            time_limit: Some(60.),
+            .. Config::default()
+        };
+        for (config, queued) in [(endless, false), (timed, true)] {
+            let mut server = Server::new(0, config, Arc::new(ServerSocket::new("127.0.0.1:0")));
+            let handle = server.handle();
+            let clients: Vec<_> = (0..3).map(|_| UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+            let addrs: Vec<_> = clients.iter().map(|c| c.local_addr().unwrap()).collect();
+            handle.handle(addrs[0], ClientPacket::Connect);
+            handle.handle(addrs[1], ClientPacket::Connect);
+            start_match(&mut server);
+
+            handle.handle(addrs[2], ClientPacket::Connect);
+            let i = server.connections.lock().unwrap()[&addrs[2]];
+            assert_eq!(!server.players.lock().unwrap()[&i].is_alive(), queued);
         }
-        // listener.join();
     }
 }
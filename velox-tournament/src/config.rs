@@ -0,0 +1,92 @@
+use std::env::args;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Everyone plays everyone once
+    RoundRobin,
+    /// Each round pairs up bots with similar scores
+    Swiss,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Names of the bots taking part
+    pub bots: Vec<String>,
+    pub format: Format,
+    /// Rounds of a Swiss tournament, enough to find a clear winner if `None`
+    pub rounds: Option<u32>,
+    /// Simulated seconds each match lasts
+    pub duration: f32,
+    /// Simulated seconds per tick
+    pub tick: f32,
+    /// Milliseconds bots get to answer each tick, every tick waits for them if `None`
+    ///
+    /// Either way a bot that takes longer than `velox_core::control::STUCK_TIME` is out of the match.
+    pub think_time: Option<u64>,
+    /// Seconds a dead ship waits before respawning
+    pub respawn_time: f32,
+    /// Seed the seed of every match is drawn from
+    pub seed: u32,
+    /// Folder a replay of every match is saved to, if any
+    pub replays: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bots: Vec::new(),
+            format: Format::RoundRobin,
+            rounds: None,
+            duration: 120.,
+            tick: 1. / 60.,
+            think_time: None,
+            respawn_time: 3.,
+            seed: 0,
+            replays: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads options of the form `--name value` from the command line, anything else is a bot
+    pub fn from_args() -> Self {
+        let mut config = Config::default();
+        let mut args = args().skip(1);
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                config.bots.push(arg);
+                continue
+            }
+            let value = args.next();
+            match (&*arg, value.as_deref()) {
+                ("--format", Some(v)) => config.format = parse_format(v),
+                ("--rounds", Some(v)) => config.rounds = Some(parse(&arg, v)),
+                ("--duration", Some(v)) => config.duration = parse(&arg, v),
+                ("--tick", Some(v)) => config.tick = parse(&arg, v),
+                ("--think-time", Some(v)) => config.think_time = Some(parse(&arg, v)),
+                ("--respawn-time", Some(v)) => config.respawn_time = parse(&arg, v),
+                ("--seed", Some(v)) => config.seed = parse(&arg, v),
+                ("--replays", Some(v)) => config.replays = Some(v.to_owned()),
+                _ => panic!("Unknown or incomplete option {}", arg),
+            }
+        }
+
+        config
+    }
+}
+
+fn parse_format(value: &str) -> Format {
+    match value {
+        "round-robin" => Format::RoundRobin,
+        "swiss" => Format::Swiss,
+        _ => panic!("Unknown tournament format {:?}", value),
+    }
+}
+
+fn parse<T: ::std::str::FromStr>(arg: &str, value: &str) -> T {
+    match value.parse() {
+        Ok(v) => v,
+        Err(_) => panic!("Invalid value {:?} for {}", value, arg),
+    }
+}
@@ -0,0 +1,236 @@
+extern crate velox_core;
+extern crate rand;
+
+use std::path::Path;
+use std::thread;
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use velox_core::world::{Rules, seeded_rng};
+use velox_core::arena::Arena;
+use velox_core::control::load;
+use velox_core::clock::Clock;
+
+use rand::Rng;
+
+mod config;
+mod rating;
+
+use config::{Config, Format};
+use rating::{Glicko, INITIAL_RATING, elo_update};
+
+struct Standing {
+    name: String,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+    /// 1 for every win or bye and a half for every draw
+    points: f32,
+    /// Sum of the in-game scores of every match
+    score: i32,
+    elo: f32,
+    glicko: Glicko,
+    /// Who they've already played
+    opponents: Vec<usize>,
+    byes: u32,
+}
+
+impl Standing {
+    fn new(name: String) -> Self {
+        Standing {
+            name,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            points: 0.,
+            score: 0,
+            elo: INITIAL_RATING,
+            glicko: Glicko::default(),
+            opponents: Vec::new(),
+            byes: 0,
+        }
+    }
+    fn played(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+    /// Compares by how well they're doing, best first
+    fn rank(&self, other: &Self) -> Ordering {
+        other.points.partial_cmp(&self.points).unwrap()
+            .then(other.glicko.rating.partial_cmp(&self.glicko.rating).unwrap())
+    }
+}
+
+/// Pairings for every round where everyone meets everyone once
+fn round_robin(n: usize) -> Vec<Vec<(usize, usize)>> {
+    let mut seats: Vec<_> = (0..n).map(Some).collect();
+    if n % 2 == 1 {
+        seats.push(None);
+    }
+    let m = seats.len();
+
+    (1..m).map(|_| {
+        let round = (0..m / 2)
+            .filter_map(|k| match (seats[k], seats[m - 1 - k]) {
+                (Some(a), Some(b)) => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+        let last = seats.pop().unwrap();
+        seats.insert(1, last);
+        round
+    }).collect()
+}
+
+/// Pairs up bots with similar standings who haven't met yet
+///
+/// With an odd number of bots, the lowest ranked one with the fewest byes sits the round out.
+fn swiss_round(standings: &[Standing]) -> (Vec<(usize, usize)>, Option<usize>) {
+    let mut order: Vec<_> = (0..standings.len()).collect();
+    order.sort_by(|&a, &b| standings[a].rank(&standings[b]));
+
+    let bye = if order.len() % 2 == 1 {
+        let (k, _) = order.iter().enumerate().rev()
+            .min_by_key(|&(_, &i)| standings[i].byes)
+            .unwrap();
+        Some(order.remove(k))
+    } else {
+        None
+    };
+
+    let mut pairs = Vec::new();
+    while !order.is_empty() {
+        let a = order.remove(0);
+        let k = order.iter().position(|b| !standings[a].opponents.contains(b)).unwrap_or(0);
+        pairs.push((a, order.remove(k)));
+    }
+    (pairs, bye)
+}
+
+/// `name` with anything that might not belong in a file name replaced
+fn file_name(name: &str) -> String {
+    name.trim().chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+/// Plays a match between two bots and returns their scores
+fn play(config: &Config, a: &str, b: &str, seed: u32, replay: Option<String>) -> (i32, i32) {
+    let rules = Rules {
+        respawn_time: config.respawn_time,
+        .. Rules::default()
+    };
+    let mut arena = Arena::new(rules, seed);
+    arena.think_time = config.think_time.map(Duration::from_millis);
+    let ia = arena.add(load(a).unwrap(), None);
+    let ib = arena.add(load(b).unwrap(), None);
+    if let Some(path) = replay {
+        if let Err(e) = arena.record(&path, &format!("{} vs {}", a, b)) {
+            println!("Couldn't record {}: {}", path, e);
+        }
+    }
+
+    arena.run(config.duration, &mut Clock::virtual_time(config.tick));
+    (arena.score(ia), arena.score(ib))
+}
+
+/// Plays every match of a round at once and updates the standings
+fn play_round<R: Rng>(config: &Config, rng: &mut R, round: u32, pairs: &[(usize, usize)], standings: &mut [Standing]) {
+    let matches: Vec<_> = pairs.iter().enumerate().map(|(n, &(a, b))| {
+        let config = config.clone();
+        let (name_a, name_b) = (standings[a].name.clone(), standings[b].name.clone());
+        let seed = rng.gen();
+        let replay = config.replays.as_ref().map(|dir| {
+            Path::new(dir)
+                .join(format!("{}-{}-{}-vs-{}.vxr", round, n + 1, file_name(&name_a), file_name(&name_b)))
+                .to_string_lossy().into_owned()
+        });
+        thread::spawn(move || play(&config, &name_a, &name_b, seed, replay))
+    }).collect();
+    let scores: Vec<_> = matches.into_iter().map(|m| m.join().unwrap()).collect();
+
+    let before: Vec<_> = standings.iter().map(|s| s.glicko).collect();
+    let mut results = vec![Vec::new(); standings.len()];
+
+    for (&(a, b), &(score_a, score_b)) in pairs.iter().zip(&scores) {
+        println!("Round {}: {} {} - {} {}", round, standings[a].name, score_a, score_b, standings[b].name);
+        let result = match score_a.cmp(&score_b) {
+            Ordering::Greater => 1.,
+            Ordering::Equal => 0.5,
+            Ordering::Less => 0.,
+        };
+        for &(i, opponent, s, score) in &[(a, b, result, score_a), (b, a, 1. - result, score_b)] {
+            let standing = &mut standings[i];
+            standing.points += s;
+            standing.score += score;
+            standing.opponents.push(opponent);
+            match s {
+                s if s > 0.5 => standing.wins += 1,
+                s if s < 0.5 => standing.losses += 1,
+                _ => standing.draws += 1,
+            }
+            results[i].push((before[opponent], s));
+        }
+
+        let (mut elo_a, mut elo_b) = (standings[a].elo, standings[b].elo);
+        elo_update(&mut elo_a, &mut elo_b, result);
+        standings[a].elo = elo_a;
+        standings[b].elo = elo_b;
+    }
+
+    for (standing, results) in standings.iter_mut().zip(results) {
+        standing.glicko = standing.glicko.rate(&results);
+    }
+}
+
+fn print_standings(standings: &[Standing]) {
+    let mut order: Vec<_> = standings.iter().collect();
+    order.sort_by(|a, b| a.rank(b));
+
+    let width = standings.iter().map(|s| s.name.len()).max().unwrap_or(0).max(16);
+
+    println!("{:>4}  {:<w$} {:>3} {:>3} {:>3} {:>3} {:>5} {:>6} {:>6} {:>11}",
+        "#", "Bot", "P", "W", "D", "L", "Pts", "Score", "Elo", "Glicko", w = width);
+    for (n, s) in order.into_iter().enumerate() {
+        println!("{:>4}  {:<w$} {:>3} {:>3} {:>3} {:>3} {:>5.1} {:>6} {:>6.0} {:>5.0} ±{:<4.0}",
+            n + 1, s.name, s.played(), s.wins, s.draws, s.losses, s.points, s.score,
+            s.elo, s.glicko.rating, s.glicko.deviation, w = width);
+    }
+}
+
+fn main() {
+    let config = Config::from_args();
+    if config.bots.len() < 2 {
+        println!("Usage: velox-tournament [options] BOT BOT...");
+        println!("Every bot is the name of a built-in one or a command running a ship program");
+        return
+    }
+    for name in &config.bots {
+        if let Err(e) = load(name) {
+            panic!("Couldn't load bot {:?}: {}", name, e);
+        }
+    }
+
+    let mut standings: Vec<_> = config.bots.iter().cloned().map(Standing::new).collect();
+    let mut rng = seeded_rng(config.seed);
+
+    match config.format {
+        Format::RoundRobin => {
+            for (round, pairs) in round_robin(standings.len()).into_iter().enumerate() {
+                play_round(&config, &mut rng, round as u32 + 1, &pairs, &mut standings);
+            }
+        }
+        Format::Swiss => {
+            let rounds = config.rounds.unwrap_or_else(|| (standings.len() as f32).log2().ceil() as u32);
+            for round in 1..=rounds {
+                let (pairs, bye) = swiss_round(&standings);
+                if let Some(i) = bye {
+                    println!("Round {}: {} has a bye", round, standings[i].name);
+                    standings[i].points += 1.;
+                    standings[i].byes += 1;
+                }
+                play_round(&config, &mut rng, round, &pairs, &mut standings);
+            }
+        }
+    }
+
+    println!();
+    print_standings(&standings);
+}
@@ -0,0 +1,120 @@
+use std::f32::consts::{PI, LN_10};
+
+/// Rating every bot starts out with
+pub const INITIAL_RATING: f32 = 1500.;
+/// How much one match can move an Elo rating
+const ELO_K: f32 = 32.;
+/// Deviation of a bot nothing is known about
+const INITIAL_DEVIATION: f32 = 350.;
+/// How much the deviation grows back between rounds
+const DEVIATION_GROWTH: f32 = 30.;
+
+/// Chance of a rated `a` beating a rated `b` according to Elo
+pub fn elo_expected(a: f32, b: f32) -> f32 {
+    1. / (1. + 10f32.powf((b - a) / 400.))
+}
+
+/// Moves the Elo ratings of `a` and `b` after `a` scored `score` against `b`
+///
+/// `score` is 1 for a win, 0.5 for a draw and 0 for a loss.
+pub fn elo_update(a: &mut f32, b: &mut f32, score: f32) {
+    let change = ELO_K * (score - elo_expected(*a, *b));
+    *a += change;
+    *b -= change;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glicko {
+    pub rating: f32,
+    /// How unsure the rating is
+    pub deviation: f32,
+}
+
+impl Default for Glicko {
+    fn default() -> Self {
+        Glicko {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+        }
+    }
+}
+
+const Q: f32 = LN_10 / 400.;
+
+fn g(deviation: f32) -> f32 {
+    1. / (1. + 3. * Q * Q * deviation * deviation / (PI * PI)).sqrt()
+}
+
+impl Glicko {
+    /// The rating after a rating period with these opponents and scores
+    ///
+    /// Every result should be against the opponent as they were at the start of the period.
+    pub fn rate(self, results: &[(Glicko, f32)]) -> Glicko {
+        let deviation = (self.deviation * self.deviation + DEVIATION_GROWTH * DEVIATION_GROWTH)
+            .sqrt().min(INITIAL_DEVIATION);
+        if results.is_empty() {
+            return Glicko {
+                deviation,
+                .. self
+            };
+        }
+
+        let mut d_inv = 0.;
+        let mut change = 0.;
+        for &(opponent, score) in results {
+            let g = g(opponent.deviation);
+            let expected = 1. / (1. + 10f32.powf(-g * (self.rating - opponent.rating) / 400.));
+            d_inv += Q * Q * g * g * expected * (1. - expected);
+            change += g * (score - expected);
+        }
+        let precision = 1. / (deviation * deviation) + d_inv;
+
+        Glicko {
+            rating: self.rating + Q / precision * change,
+            deviation: (1. / precision).sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo_is_zero_sum() {
+        assert_eq!(elo_expected(1500., 1500.), 0.5);
+        assert!((elo_expected(1700., 1500.) + elo_expected(1500., 1700.) - 1.).abs() < 1e-6);
+        assert!((elo_expected(1900., 1500.) - 10. / 11.).abs() < 1e-6);
+
+        let (mut a, mut b) = (1500., 1500.);
+        elo_update(&mut a, &mut b, 1.);
+        assert_eq!((a, b), (1516., 1484.));
+        elo_update(&mut a, &mut b, 0.5);
+        assert!(a < 1516. && a + b == 3000.);
+    }
+
+    #[test]
+    fn glickmans_example() {
+        // The example starts the period at 200, which growing the deviation lands on
+        let player = Glicko {
+            rating: 1500.,
+            deviation: (200f32 * 200. - DEVIATION_GROWTH * DEVIATION_GROWTH).sqrt(),
+        };
+        let opponent = |rating, deviation| Glicko { rating, deviation };
+        let rated = player.rate(&[
+            (opponent(1400., 30.), 1.),
+            (opponent(1550., 100.), 0.),
+            (opponent(1700., 300.), 0.),
+        ]);
+        assert!((rated.rating - 1464.).abs() < 0.5, "{:?}", rated);
+        assert!((rated.deviation - 151.4).abs() < 0.1, "{:?}", rated);
+    }
+
+    #[test]
+    fn deviation_grows_without_games() {
+        let rated = Glicko { rating: 1600., deviation: 40. }.rate(&[]);
+        assert_eq!(rated.rating, 1600.);
+        assert!((rated.deviation - 50.).abs() < 1e-3);
+        assert_eq!(Glicko::default().rate(&[]).deviation, INITIAL_DEVIATION);
+    }
+}
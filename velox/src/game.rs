@@ -1,9 +1,18 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-use velox_core::obj::{PhysicsObject, RotatableObject};
+use velox_core::obj::{Vect, Vector2, PhysicsObject, RotatableObject};
 use velox_core::net::*;
+use velox_core::weapon::WeaponId;
+use velox_core::damage::Status;
+use velox_core::control::DebugOutput;
+use velox_core::score::Stats;
+use velox_core::replay::{Replay, Playback};
+use velox_core::world::Event as WorldEvent;
+
+use hud;
+use font;
 
 use piston_window::*;
 
@@ -47,32 +56,96 @@ pub fn pos_rot_mat(x: f64, y: f64, width: f64, height: f64, w: f64, h: f64, rot:
 pub struct SpaceShooter {
     window: PistonWindow,
     assets: Assets,
-    socket: Arc<ClientSocket>,
+    /// Connection to the server, unless a replay is being watched
+    socket: Option<Arc<ClientSocket>>,
+    playback: Option<Playback>,
     planets: Arc<Mutex<BTreeMap<Idx, PhysicsObject>>>,
     players: Arc<Mutex<BTreeMap<Idx, RotatableObject>>>,
     lasers: Arc<Mutex<BTreeMap<Idx, RotatableObject>>>,
-    health: Arc<Mutex<u8>>,
+    status: Arc<Mutex<Status>>,
+    /// Where ships blew up and how many seconds ago
+    explosions: Arc<Mutex<Vec<(Vect, f32)>>>,
+    /// Who killed who lately and how many seconds ago, newest first
+    kill_feed: Arc<Mutex<VecDeque<(String, f32)>>>,
+    /// Score and stats of every player
+    scoreboard: Arc<Mutex<BTreeMap<Idx, (i32, Stats)>>>,
+    /// Phase of the match and seconds left of it
+    phase: Arc<Mutex<(MatchPhase, Option<f32>)>>,
+    /// Our own ship
+    own_id: Arc<Mutex<Option<Idx>>>,
+    /// Team of every player that's on one
+    teams: Arc<Mutex<BTreeMap<Idx, TeamId>>>,
+    /// What the autopilot flying our ship showed about its latest tick
+    debug: Arc<Mutex<DebugOutput>>,
+    /// Watching without a ship
+    spectating: bool,
 }
 
+const EXPLOSION_TIME: f32 = 0.6;
+/// How long kills stay in the feed and how many are shown at once
+const KILL_FEED_TIME: f32 = 6.;
+const KILL_FEED_LINES: usize = 5;
+/// How fast the free spectator camera pans
+const CAMERA_SPEED: f32 = 400.;
+const TEAM_COLOURS: [[f32; 4]; 4] = [
+    [1., 0.4, 0.4, 1.],
+    [0.4, 0.6, 1., 1.],
+    [0.4, 1., 0.4, 1.],
+    [1., 1., 0.3, 1.],
+];
+
 impl SpaceShooter {
-    pub fn new(mut window: PistonWindow, server: &str) -> Self {
+    pub fn new(window: PistonWindow, socket: ClientSocket, spectating: bool) -> Self {
+        SpaceShooter {
+            socket: Some(Arc::new(socket)),
+            spectating,
+            .. Self::offline(window)
+        }
+    }
+    /// Watches a recorded match instead of connecting anywhere
+    pub fn replay(window: PistonWindow, replay: Replay) -> Self {
+        SpaceShooter {
+            playback: Some(Playback::new(replay)),
+            .. Self::offline(window)
+        }
+    }
+    fn offline(mut window: PistonWindow) -> Self {
         SpaceShooter {
             assets: Assets::new(&mut window),
             window: window,
             planets: Arc::default(),
             players: Arc::default(),
             lasers: Arc::default(),
-            health: Arc::default(),
-            socket: Arc::new(ClientSocket::new(server))
+            status: Arc::new(Mutex::new(Status::ship())),
+            explosions: Arc::default(),
+            kill_feed: Arc::default(),
+            scoreboard: Arc::default(),
+            phase: Arc::new(Mutex::new((MatchPhase::Waiting, None))),
+            own_id: Arc::default(),
+            teams: Arc::default(),
+            debug: Arc::default(),
+            spectating: true,
+            socket: None,
+            playback: None,
         }
     }
     // YORO
     pub fn start_network_thread(&self) {
-        let socket = self.socket.clone();
+        let socket = match self.socket {
+            Some(ref socket) => socket.clone(),
+            None => return,
+        };
         let lasers_m = self.lasers.clone();
         let planets_m = self.planets.clone();
         let players_m = self.players.clone();
-        let health_m = self.health.clone();
+        let status_m = self.status.clone();
+        let explosions_m = self.explosions.clone();
+        let kill_feed_m = self.kill_feed.clone();
+        let scoreboard_m = self.scoreboard.clone();
+        let phase_m = self.phase.clone();
+        let own_id_m = self.own_id.clone();
+        let teams_m = self.teams.clone();
+        let debug_m = self.debug.clone();
         thread::spawn(move || {
             loop {
                 let p = socket.recv();
@@ -98,6 +171,8 @@ impl SpaceShooter {
                     }
                     Ok(ServerPacket::DeletePlayer(player_id)) => {
                         players_m.lock().unwrap().remove(&player_id);
+                        scoreboard_m.lock().unwrap().remove(&player_id);
+                        teams_m.lock().unwrap().remove(&player_id);
                     }
                     Ok(ServerPacket::DeletePlanets(ps)) => {
                         let mut planets = planets_m.lock().unwrap();
@@ -111,8 +186,55 @@ impl SpaceShooter {
                             lasers.remove(&i);
                         }
                     }
-                    Ok(ServerPacket::UpdateHealth(h)) => {
-                        *health_m.lock().unwrap() = h;
+                    Ok(ServerPacket::UpdateStatus(s)) => {
+                        *status_m.lock().unwrap() = s;
+                    }
+                    Ok(ServerPacket::Damage{..}) => (),
+                    Ok(ServerPacket::Died{player, killer, pos, ..}) => {
+                        players_m.lock().unwrap().remove(&player);
+                        explosions_m.lock().unwrap().push((pos, 0.));
+                        report_kill(&mut kill_feed_m.lock().unwrap(), player, killer);
+                    }
+                    Ok(ServerPacket::Respawned(i, p)) => {
+                        players_m.lock().unwrap().insert(i, p);
+                    }
+                    Ok(ServerPacket::Scoreboard(stats)) => {
+                        scoreboard_m.lock().unwrap().extend(stats.into_iter().map(|(i, score, s)| (i, (score, s))));
+                    }
+                    Ok(ServerPacket::GameMode{mode, teams, time_limit}) => {
+                        println!("Playing {:?} with {} teams, time limit {:?}", mode, teams, time_limit);
+                    }
+                    Ok(ServerPacket::Joined{id, team}) => {
+                        println!("Joined as player {} on team {:?}", id, team);
+                        *own_id_m.lock().unwrap() = Some(id);
+                    }
+                    Ok(ServerPacket::Team(i, team)) => {
+                        let mut teams = teams_m.lock().unwrap();
+                        match team {
+                            Some(t) => teams.insert(i, t),
+                            None => teams.remove(&i),
+                        };
+                    }
+                    Ok(ServerPacket::MatchOver(outcome)) => {
+                        println!("Match over! {:?}", outcome);
+                    }
+                    Ok(ServerPacket::Phase{phase, time_left}) => {
+                        *phase_m.lock().unwrap() = (phase, time_left);
+                    }
+                    Ok(ServerPacket::Rooms(rooms)) => {
+                        println!("Couldn't join, rooms are:");
+                        for room in rooms {
+                            println!("{:>3} {:16} {}/{} players", room.id, room.name, room.players, room.max_players);
+                        }
+                    }
+                    Ok(ServerPacket::Debug{first, pieces}) => {
+                        let mut debug = debug_m.lock().unwrap();
+                        if first {
+                            *debug = DebugOutput::default();
+                        }
+                        for piece in pieces {
+                            debug.add(piece);
+                        }
                     }
                     Ok(ServerPacket::DisconnectAck) => break,
                     Err(e) => println!("Error! {:?}", e),
@@ -128,13 +250,31 @@ impl SpaceShooter {
             planets,
             players,
             lasers,
-            health,
+            status,
+            explosions,
+            kill_feed,
+            scoreboard,
+            phase,
+            own_id,
+            teams,
+            debug,
+            spectating,
             socket,
+            mut playback,
         } = self;
         let (mut up, mut down, mut left, mut right)
             = (false, false, false, false);
 
         let mut last_impulse = 0.;
+        let mut weapon: WeaponId = 0;
+        let mut show_scoreboard = false;
+        // Where the view is centred, only moved when spectating
+        let mut camera: Vect = Vector2(0., 0.);
+        let mut following: Option<Idx> = None;
+        let mut paused = false;
+        let mut speed = 1f32;
+        let mut autopilot = false;
+        let mut overlay = false;
 
         while let Some(e) = window.next() {
             match e {
@@ -142,9 +282,43 @@ impl SpaceShooter {
                     let press = b.state == ButtonState::Press;
 
                     match b.button {
-                        Button::Keyboard(Key::Space) if press => {
-                            socket.send(ClientPacket::Shoot).unwrap();
+                        Button::Keyboard(Key::F) if press && spectating => {
+                            // Follows the next ship along, wrapping around
+                            let players = players.lock().unwrap();
+                            following = following.and_then(|f| players.range(f+1..).next())
+                                .or_else(|| players.iter().next())
+                                .map(|(&i, _)| i);
+                        }
+                        Button::Keyboard(Key::G) if press && spectating => following = None,
+                        Button::Keyboard(Key::N) if press && paused => {
+                            if let Some(ref mut playback) = playback {
+                                playback.step();
+                            }
+                        }
+                        Button::Keyboard(Key::Comma) | Button::Keyboard(Key::Period) if press => {
+                            if let Some(ref mut playback) = playback {
+                                let skip = if b.button == Button::Keyboard(Key::Comma) {-5.} else {5.};
+                                let time = playback.time();
+                                playback.seek((time + skip).max(0.));
+                            }
+                        }
+                        Button::Keyboard(Key::LeftBracket) if press => speed = (speed / 2.).max(0.125),
+                        Button::Keyboard(Key::RightBracket) if press => speed = (speed * 2.).min(8.),
+                        Button::Keyboard(Key::Space) if press && playback.is_some() => paused = !paused,
+                        Button::Keyboard(Key::Space) if press && !spectating => {
+                            socket.as_ref().unwrap().send(ClientPacket::Shoot{weapon}).unwrap();
+                        }
+                        Button::Keyboard(Key::P) if press && !spectating => {
+                            autopilot = !autopilot;
+                            socket.as_ref().unwrap().send(ClientPacket::Autopilot(autopilot)).unwrap();
                         }
+                        Button::Keyboard(Key::O) if press => overlay = !overlay,
+                        Button::Keyboard(Key::Tab) => show_scoreboard = press,
+                        Button::Keyboard(Key::D1) => weapon = 0,
+                        Button::Keyboard(Key::D2) => weapon = 1,
+                        Button::Keyboard(Key::D3) => weapon = 2,
+                        Button::Keyboard(Key::D4) => weapon = 3,
+                        Button::Keyboard(Key::D5) => weapon = 4,
                         Button::Keyboard(Key::J) if press => {
                             println!("Planets: {:#?}", *planets.lock().unwrap());
                             println!("Players: {:#?}", *players.lock().unwrap());
@@ -162,28 +336,81 @@ impl SpaceShooter {
                     let h = r.height as f64/2.;
                     window.draw_2d(&e, |c, g| {
                         clear([0., 0., 0., 1.], g);
+                        let view = c.transform.trans(-camera.0 as f64, -camera.1 as f64);
 
                         for planet in planets.lock().unwrap().values() {
                             let (x, y) = planet.pos().into();
-                            image(&assets.planet, c.transform.append_transform(pos_mat(
+                            image(&assets.planet, view.append_transform(pos_mat(
                                 x as f64, y as f64, 32., 32., w, h)), g)
                         }
 
-                        for player in players.lock().unwrap().values() {
+                        let teams = teams.lock().unwrap();
+                        for (i, player) in players.lock().unwrap().iter() {
                             let (x, y) = player.pos().into();
-                            image(&assets.ship, c.transform.append_transform(pos_rot_mat(
+                            let colour = teams.get(i).map(|&t| TEAM_COLOURS[t as usize % TEAM_COLOURS.len()]).unwrap_or([1.; 4]);
+                            Image::new_color(colour).draw(&assets.ship, &c.draw_state, view.append_transform(pos_rot_mat(
                                 x as f64, y as f64, 16., 16., w, h, player.rotation as f64)), g)
                         }
 
                         for laser in lasers.lock().unwrap().values() {
                             let (x, y) = laser.pos().into();
-                            image(&assets.laser, c.transform.append_transform(pos_rot_mat(
+                            image(&assets.laser, view.append_transform(pos_rot_mat(
                                 x as f64, y as f64, 16., 16., w, h, laser.rotation as f64)), g)
                         }
 
-                        let hp = *health.lock().unwrap();
-                        rectangle([0.77, 0.77, 0.77, 0.6], [0., 0., 160., 40.], c.transform, g);
-                        rectangle([0., 1., 0., 0.6], [10., 5., 30.*hp as f64, 20.], c.transform, g);
+                        for &(pos, age) in explosions.lock().unwrap().iter() {
+                            let (x, y) = pos.into();
+                            let r = 16. + 48. * (age / EXPLOSION_TIME) as f64;
+                            let alpha = 1. - age / EXPLOSION_TIME;
+                            ellipse([1., 0.6, 0.1, alpha], [x as f64 + w - r, y as f64 + h - r, 2.*r, 2.*r], view, g);
+                        }
+
+                        if overlay && autopilot {
+                            let own_id = *own_id.lock().unwrap();
+                            if let Some(ship) = own_id.and_then(|i| players.lock().unwrap().get(&i).map(|p| p.pos())) {
+                                let debug = debug.lock().unwrap();
+                                velox_hud::debug(&debug, &debug.log, ship, view.trans(w, h), g);
+                            }
+                        }
+
+                        if spectating {
+                            let label = match following {
+                                Some(i) => format!("FOLLOWING {}", i),
+                                None => "SPECTATING".to_owned(),
+                            };
+                            font::text(&label, [1., 1., 1., 1.], 10., 10., 2., c.transform, g);
+                        } else {
+                            velox_hud::status(&status.lock().unwrap(), weapon, c.transform, g);
+                            if autopilot {
+                                font::text("AUTOPILOT", [1., 1., 1., 1.], 10., 60., 2., c.transform, g);
+                            }
+                        }
+
+                        let (phase, time_left) = *phase.lock().unwrap();
+                        let seconds = time_left.map(|t| t.max(0.).ceil() as u32).unwrap_or(0);
+                        let (banner, scale) = match phase {
+                            _ if paused => ("PAUSED".to_owned(), 3.),
+                            _ if playback.is_some() => {
+                                let playback = playback.as_ref().unwrap();
+                                (format!("{:.1}/{:.1} X{}", playback.time(), playback.duration(), speed), 3.)
+                            }
+                            MatchPhase::Waiting => ("WAITING FOR PLAYERS".to_owned(), 3.),
+                            MatchPhase::Warmup => (format!("WARMUP {}", seconds), 3.),
+                            MatchPhase::Countdown => (seconds.to_string(), 8.),
+                            MatchPhase::Running => (String::new(), 3.),
+                            MatchPhase::Results(Outcome::Player(i)) => (format!("PLAYER {} WINS!", i), 4.),
+                            MatchPhase::Results(Outcome::Team(t)) => (format!("TEAM {} WINS!", t), 4.),
+                            MatchPhase::Results(Outcome::Draw) => ("DRAW".to_owned(), 4.),
+                        };
+                        let banner_width = 4. * scale * banner.len() as f64;
+                        font::text(&banner, [1., 1., 1., 1.], w - banner_width / 2., 16., scale, c.transform, g);
+
+                        hud::kill_feed(kill_feed.lock().unwrap().iter(), 2. * w - 10., 10., c.transform, g);
+
+                        let results = matches!(phase, MatchPhase::Results(_));
+                        if show_scoreboard || results {
+                            hud::scoreboard(scoreboard.lock().unwrap().iter(), w, h, c.transform, g);
+                        }
                     });
                 }
                 Event::Loop(Loop::Update(u)) => {
@@ -200,16 +427,26 @@ impl SpaceShooter {
                     if left {
                         rotation -= 1.;
                     }
+                    if spectating {
+                        let players = players.lock().unwrap();
+                        match following.and_then(|i| players.get(&i)) {
+                            Some(player) => camera = player.pos(),
+                            None => camera += CAMERA_SPEED * u.dt as f32 * Vector2(rotation, -impulse),
+                        }
+                    }
+
                     let rot;
-                    if rotation != 0. {
+                    if spectating {
+                        rot = false;
+                    } else if rotation != 0. {
                         rot = true;
-                        socket.send(ClientPacket::PlayerRotate(rotation * 2. * u.dt as f32)).unwrap();
+                        socket.as_ref().unwrap().send(ClientPacket::PlayerRotate(rotation * 2. * u.dt as f32)).unwrap();
                     } else {
                         rot = false;
                     }
-                    if rot || impulse != last_impulse {
+                    if !spectating && (rot || impulse != last_impulse) {
                         last_impulse = impulse;
-                        socket.send(ClientPacket::PlayerImpulse(impulse * 150.)).unwrap();
+                        socket.as_ref().unwrap().send(ClientPacket::PlayerImpulse(impulse * 150.)).unwrap();
                     }
 
                     for planet in planets.lock().unwrap().values_mut() {
@@ -226,14 +463,60 @@ impl SpaceShooter {
                         laser.update(u.dt as f32);
                         laser.stay_in_bounds();
                     }
+
+                    let mut explosions = explosions.lock().unwrap();
+                    for explosion in explosions.iter_mut() {
+                        explosion.1 += u.dt as f32;
+                    }
+                    explosions.retain(|&(_, age)| age < EXPLOSION_TIME);
+
+                    let mut kill_feed = kill_feed.lock().unwrap();
+                    for kill in kill_feed.iter_mut() {
+                        kill.1 += u.dt as f32;
+                    }
+                    kill_feed.retain(|&(_, age)| age < KILL_FEED_TIME);
+
+                    if let Some(ref mut t) = phase.lock().unwrap().1 {
+                        *t -= u.dt as f32;
+                    }
+
+                    if let Some(ref mut playback) = playback {
+                        let events = if paused {Vec::new()} else {playback.advance(speed * u.dt as f32)};
+                        for event in events {
+                            if let WorldEvent::Died{player, killer, pos, ..} = event {
+                                explosions.push((pos, 0.));
+                                report_kill(&mut kill_feed, player, killer);
+                            }
+                        }
+                        let world = playback.world();
+                        *planets.lock().unwrap() = world.planets.iter().map(|(&i, p)| (i, p.obj)).collect();
+                        *lasers.lock().unwrap() = world.lasers.iter().map(|(&i, l)| (i, l.obj)).collect();
+                        *players.lock().unwrap() = world.players.iter().filter(|&(_, p)| p.is_alive())
+                            .map(|(&i, p)| (i, p.obj)).collect();
+                        *teams.lock().unwrap() = world.players.iter().filter_map(|(&i, p)| p.team.map(|t| (i, t))).collect();
+                        let mode = playback.header().mode;
+                        *scoreboard.lock().unwrap() = world.players.iter().map(|(&i, p)| (i, (mode.score(&p.stats), p.stats))).collect();
+                    }
                 }
-                Event::Input(Input::Close(_)) => {socket.send(ClientPacket::Disconnect).unwrap();}
+                Event::Input(Input::Close(_)) => if let Some(ref socket) = socket {
+                    socket.send(ClientPacket::Disconnect).unwrap();
+                },
                 _ => {} // Catch uninteresting events
             }
         }
     }
 }
 
+/// Adds a line about `player` dying to the top of the kill feed
+fn report_kill(feed: &mut VecDeque<(String, f32)>, player: Idx, killer: Option<Idx>) {
+    let line = match killer {
+        Some(killer) if killer != player => format!("{} killed {}", killer, player),
+        _ => format!("{} died", player),
+    };
+    feed.push_front((line, 0.));
+    feed.truncate(KILL_FEED_LINES);
+}
+
 // fn collision(relative_velocity: Vect, dist: Vect) -> Vect{
 // (2. * m2)/(m1 + m2) * */ relative_velocity.dot(dist) / dist.length_squared() * dist
 // }
@@ -0,0 +1,52 @@
+use std::cmp::Reverse;
+
+use piston_window::*;
+
+use velox_core::net::Idx;
+use velox_core::score::Stats;
+
+use font::text;
+
+const COLUMNS: [&str; 7] = ["ID", "SC", "K", "D", "DMG", "ACC", "PL"];
+const COLUMN_WIDTH: f64 = 56.;
+const ROW_HEIGHT: f64 = 16.;
+
+/// Draws a table of everyone's score and stats, best first, centred on `(cx, cy)`
+pub fn scoreboard<'a, I, G>(stats: I, cx: f64, cy: f64, transform: math::Matrix2d, g: &mut G)
+where I: IntoIterator<Item=(&'a Idx, &'a (i32, Stats))>, G: Graphics {
+    let mut rows: Vec<_> = stats.into_iter().collect();
+    rows.sort_by_key(|&(_, &(score, _))| Reverse(score));
+
+    let width = COLUMN_WIDTH * COLUMNS.len() as f64 + 16.;
+    let height = ROW_HEIGHT * (rows.len() + 1) as f64 + 16.;
+    let (x, y) = (cx - width / 2., cy - height / 2.);
+
+    rectangle([0.1, 0.1, 0.1, 0.8], [x, y, width, height], transform, g);
+    for (c, title) in COLUMNS.iter().enumerate() {
+        text(title, [1., 1., 0., 1.], x + 8. + COLUMN_WIDTH * c as f64, y + 8., 2., transform, g);
+    }
+    for (r, &(i, &(score, ref s))) in rows.iter().enumerate() {
+        let cells = [
+            i.to_string(),
+            score.to_string(),
+            s.kills.to_string(),
+            s.deaths.to_string(),
+            s.damage_dealt.to_string(),
+            format!("{}%", (100. * s.accuracy()).round()),
+            s.planets_destroyed.to_string(),
+        ];
+        let row_y = y + 8. + ROW_HEIGHT * (r + 1) as f64;
+        for (c, cell) in cells.iter().enumerate() {
+            text(cell, [1., 1., 1., 1.], x + 8. + COLUMN_WIDTH * c as f64, row_y, 2., transform, g);
+        }
+    }
+}
+
+/// Draws the kill feed lines right aligned to `x`, going down from `y`
+pub fn kill_feed<'a, I, G>(lines: I, x: f64, y: f64, transform: math::Matrix2d, g: &mut G)
+where I: IntoIterator<Item=&'a (String, f32)>, G: Graphics {
+    for (r, (line, _)) in lines.into_iter().enumerate() {
+        let width = 8. * line.len() as f64;
+        text(line, [1., 0.8, 0.4, 1.], x - width, y + ROW_HEIGHT * r as f64, 2., transform, g);
+    }
+}
@@ -1,24 +1,87 @@
 extern crate piston_window;
 extern crate find_folder;
 extern crate velox_core;
+extern crate velox_font as font;
+extern crate velox_hud;
 
 use piston_window::*;
 
 use game::SpaceShooter;
 use std::env::args;
+use std::time::Duration;
+
+use velox_core::net::{ClientSocket, ClientPacket, ServerPacket};
+use velox_core::replay::Replay;
 
 pub use velox_core::obj::Vector2;
 
 mod game;
+mod hud;
 
 fn main() {
-    let window: PistonWindow =
-    WindowSettings::new(format!("Space Shooter {}", env!("CARGO_PKG_VERSION")), [1200, 900])
-            .exit_on_esc(true)
-            .vsync(true)
-            .build().unwrap();
+    // `replay` and `verify` followed by a replay file watch or check a recorded match
+    match args().nth(1).as_deref() {
+        Some("replay") => {
+            let this = SpaceShooter::replay(window(), load_replay());
+            return this.run()
+        }
+        Some("verify") => return verify_replay(load_replay()),
+        _ => (),
+    }
+
     let server = args().nth(1).unwrap_or_else(|| "127.0.0.1:7351".to_owned());
-    let this = SpaceShooter::new(window, &server);
+    // Either a room to join, `list` to see which there are or `spectate` followed by a room to watch
+    let mut spectating = false;
+    let socket = match args().nth(2) {
+        Some(ref room) if room == "list" => return list_rooms(&server),
+        Some(ref room) if room == "spectate" => {
+            spectating = true;
+            let socket = ClientSocket::bind(&*server);
+            let room = args().nth(3).map(|r| r.parse().expect("Room should be a number")).unwrap_or(0);
+            socket.send(ClientPacket::Spectate(room)).unwrap();
+            socket
+        }
+        Some(room) => ClientSocket::join(&*server, room.parse().expect("Room should be a number")),
+        None => ClientSocket::new(&*server),
+    };
+
+    let this = SpaceShooter::new(window(), socket, spectating);
     this.start_network_thread();
     this.run();
 }
+
+fn window() -> PistonWindow {
+    WindowSettings::new(format!("Space Shooter {}", env!("CARGO_PKG_VERSION")), [1200, 900])
+            .exit_on_esc(true)
+            .vsync(true)
+            .build().unwrap()
+}
+
+fn load_replay() -> Replay {
+    let path = args().nth(2).expect("Replay file missing");
+    Replay::load(&path).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path, e))
+}
+
+/// Simulates a replay again without showing it to check it comes out the same
+fn verify_replay(replay: Replay) {
+    let frames = replay.frames.len();
+    match replay.verify() {
+        Ok(keyframes) => println!("All {} keyframes over {} frames match", keyframes, frames),
+        Err(frame) => {
+            println!("Replay diverges at frame {} of {}", frame, frames);
+            ::std::process::exit(1);
+        }
+    }
+}
+
+fn list_rooms(server: &str) {
+    let socket = ClientSocket::bind(server);
+    socket.set_timeout(Some(Duration::from_secs(1))).unwrap();
+    socket.send(ClientPacket::ListRooms).unwrap();
+    while let Ok(ServerPacket::Rooms(rooms)) = socket.recv() {
+        for room in rooms {
+            println!("{:>3} {:16} {:?} {}/{} players, {} watching, {:?}", room.id, room.name, room.mode,
+                room.players, room.max_players, room.spectators, room.phase);
+        }
+    }
+}